@@ -0,0 +1,65 @@
+use core::fmt;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use globals;
+use print;
+use protocol::Error;
+use protocol::handle::Handle;
+
+static ABORTING: AtomicBool = AtomicBool::new(false);
+
+/// Reports a fatal error and terminates the application.
+///
+/// The message is written to `StdErr`, or to the fallback sink of `print!`
+/// after `ExitBootServices()`. While boot services are available, the image
+/// exits with `EFI_ABORTED` and control returns to the firmware. Otherwise
+/// there is nobody to return to, and the processor is halted.
+pub fn abort(args: fmt::Arguments) -> ! {
+    // Don't try to print again if printing is what failed.
+    if !ABORTING.swap(true, Ordering::SeqCst) {
+        print::_print(print::Stream::Err, format_args!("\n{}\n", args));
+    }
+
+    unsafe {
+        if let Some(table) = globals::BOOT_SERVICES_TABLE {
+            let _ = table.exit(Handle::from_raw(globals::IMAGE_HANDLE), Err(Error::ABORTED), None);
+        }
+    }
+
+    halt()
+}
+
+/// Stops doing anything, forever.
+///
+/// The processor sleeps until the next interrupt, and goes back to sleep after it.
+pub fn halt() -> ! {
+    loop {
+        wait_for_interrupt();
+    }
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[inline]
+fn wait_for_interrupt() {
+    unsafe { ::core::arch::asm!("hlt", options(nomem, nostack, preserves_flags)) };
+}
+
+#[cfg(target_arch = "aarch64")]
+#[inline]
+fn wait_for_interrupt() {
+    unsafe { ::core::arch::asm!("wfi", options(nomem, nostack, preserves_flags)) };
+}
+
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")))]
+#[inline]
+fn wait_for_interrupt() {
+    ::core::hint::spin_loop();
+}
+
+/// Also handles allocation failures, which panic with the size of the allocation.
+#[cfg(feature = "panic-handler")]
+#[panic_handler]
+fn panic(info: &::core::panic::PanicInfo) -> ! {
+    // Includes both the location and the message.
+    abort(format_args!("{}", info))
+}
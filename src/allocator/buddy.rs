@@ -0,0 +1,265 @@
+use core::alloc::Layout;
+use allocator::{AllocErr, FrontAllocator, PhysicalAddress};
+use core::ptr;
+use globals;
+
+struct Node {
+    next: *mut Node,
+}
+
+/// Number of block sizes, from one page up to `PAGE_SIZE << (ORDERS - 1)` (2 GiB),
+/// which still fits in `usize` on 32-bit targets.
+const ORDERS: usize = 20;
+
+/// Power-of-two buddy allocator with page granularity.
+///
+/// Every allocation takes at least one page, so this is best suited for kernels
+/// that mostly allocate page tables, stacks and other page-sized objects.
+/// Blocks are naturally aligned to their size, so any alignment up to the
+/// block size is satisfied for free.
+pub struct BuddyAllocator {
+    free: [*mut Node; ORDERS],
+}
+
+#[inline]
+fn block_size(order: usize) -> usize {
+    globals::PAGE_SIZE << order
+}
+
+impl BuddyAllocator {
+    pub const fn new() -> Self {
+        BuddyAllocator{free: [ptr::null_mut(); ORDERS]}
+    }
+
+    /// Smallest order whose blocks can hold `layout`, if any.
+    fn order_for(layout: &Layout) -> Option<usize> {
+        let size = if layout.size() > layout.align() { layout.size() } else { layout.align() };
+        (0..ORDERS).find(|&order| block_size(order) >= size)
+    }
+
+    unsafe fn push(&mut self, order: usize, addr: usize) {
+        let node = addr as *mut Node;
+        (*node).next = self.free[order];
+        self.free[order] = node;
+    }
+
+    unsafe fn pop(&mut self, order: usize) -> Option<usize> {
+        let node = self.free[order];
+        if node.is_null() {
+            return None;
+        }
+        self.free[order] = (*node).next;
+        Some(node as usize)
+    }
+
+    /// Removes the block at `addr` from the free list, returning whether it was there.
+    unsafe fn remove(&mut self, order: usize, addr: usize) -> bool {
+        let mut link: *mut *mut Node = &mut self.free[order];
+        while !(*link).is_null() {
+            if *link as usize == addr {
+                *link = (**link).next;
+                return true;
+            }
+            link = &mut (**link).next;
+        }
+        false
+    }
+
+    /// Frees a block, merging it with its buddy as long as the buddy is free.
+    unsafe fn release(&mut self, mut order: usize, mut addr: usize) {
+        while order + 1 < ORDERS {
+            let buddy = addr ^ block_size(order);
+            if !self.remove(order, buddy) {
+                break;
+            }
+            if buddy < addr {
+                addr = buddy;
+            }
+            order += 1;
+        }
+        self.push(order, addr);
+    }
+}
+
+unsafe impl FrontAllocator for BuddyAllocator {
+    unsafe fn alloc(&mut self, layout: Layout) -> Result<*mut u8, AllocErr> {
+        let order = match Self::order_for(&layout) {
+            Some(order) => order,
+            None => return Err(AllocErr),
+        };
+
+        for o in order..ORDERS {
+            if let Some(addr) = self.pop(o) {
+                // Split the block down, putting the upper halves back.
+                let mut o = o;
+                while o > order {
+                    o -= 1;
+                    self.push(o, addr + block_size(o));
+                }
+                return Ok(addr as *mut u8);
+            }
+        }
+
+        Err(AllocErr)
+    }
+
+    unsafe fn dealloc(&mut self, ptr: *mut u8, layout: Layout) {
+        let order = Self::order_for(&layout).unwrap();
+        self.release(order, ptr as usize);
+    }
+
+    unsafe fn feed_memory(&mut self, addr: PhysicalAddress, size: usize) {
+        let page_mask = globals::PAGE_SIZE - 1;
+        let end = match (addr.0 as usize).checked_add(size) {
+            Some(end) => end & !page_mask,
+            None => return,
+        };
+        let mut start = ((addr.0 as usize) + page_mask) & !page_mask;
+
+        // Cut the region into the largest naturally aligned blocks that fit.
+        while start < end {
+            let mut order = ORDERS - 1;
+            while start & (block_size(order) - 1) != 0 || end - start < block_size(order) {
+                order -= 1;
+            }
+            self.release(order, start);
+            start += block_size(order);
+        }
+    }
+
+    unsafe fn take_free_pages(&mut self, min_pages: usize) -> Option<(PhysicalAddress, usize)> {
+        for order in (0..ORDERS).rev() {
+            let pages = block_size(order) / globals::PAGE_SIZE;
+            if pages < min_pages {
+                break;
+            }
+            if let Some(addr) = self.pop(order) {
+                return Some((PhysicalAddress(addr as u64), pages));
+            }
+        }
+        None
+    }
+
+    fn region_size(&self, layout: &Layout) -> Option<usize> {
+        // A block must be aligned to its own size, and a page-aligned region
+        // this big always contains one such block.
+        Self::order_for(layout)
+            .and_then(|order| block_size(order).checked_mul(2))
+            .map(|size| size - globals::PAGE_SIZE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::alloc::Layout;
+    use core::ptr;
+    use allocator::{AllocErr, FrontAllocator, PhysicalAddress};
+    use globals::PAGE_SIZE;
+    use super::BuddyAllocator;
+
+    // Aligned to its size, so that it's fed as a single block.
+    #[repr(align(16384))]
+    struct Buffer([u8; 4 * PAGE_SIZE]);
+
+    fn pages(n: usize) -> Layout {
+        Layout::from_size_align(n * PAGE_SIZE, PAGE_SIZE).unwrap()
+    }
+
+    #[test]
+    fn split() {
+        static mut BUFFER: Buffer = Buffer([0; 4 * PAGE_SIZE]);
+        let base = unsafe { ptr::addr_of_mut!(BUFFER) as usize };
+
+        let mut a = BuddyAllocator::new();
+        unsafe {
+            a.feed_memory(PhysicalAddress(base as u64), 4 * PAGE_SIZE);
+
+            // Small allocations still take a page.
+            let small = Layout::from_size_align(8, 8).unwrap();
+            assert_eq!(a.alloc(small), Ok(base as *mut u8));
+            assert_eq!(a.alloc(pages(2)), Ok((base + 2 * PAGE_SIZE) as *mut u8));
+            assert_eq!(a.alloc(pages(1)), Ok((base + PAGE_SIZE) as *mut u8));
+            assert_eq!(a.alloc(pages(1)), Err(AllocErr));
+        }
+    }
+
+    #[test]
+    fn merge_both_halves() {
+        static mut BUFFER: Buffer = Buffer([0; 4 * PAGE_SIZE]);
+        let base = unsafe { ptr::addr_of_mut!(BUFFER) as usize };
+
+        let mut a = BuddyAllocator::new();
+        unsafe {
+            a.feed_memory(PhysicalAddress(base as u64), 4 * PAGE_SIZE);
+            let p: [*mut u8; 4] = [
+                a.alloc(pages(1)).unwrap(),
+                a.alloc(pages(1)).unwrap(),
+                a.alloc(pages(1)).unwrap(),
+                a.alloc(pages(1)).unwrap(),
+            ];
+
+            // Lower half first in one pair, upper half first in the other.
+            a.dealloc(p[0], pages(1));
+            a.dealloc(p[1], pages(1));
+            a.dealloc(p[3], pages(1));
+            a.dealloc(p[2], pages(1));
+
+            assert_eq!(a.alloc(pages(4)), Ok(base as *mut u8));
+            assert_eq!(a.alloc(pages(1)), Err(AllocErr));
+        }
+    }
+
+    #[test]
+    fn unaligned_region() {
+        static mut BUFFER: Buffer = Buffer([0; 4 * PAGE_SIZE]);
+        let base = unsafe { ptr::addr_of_mut!(BUFFER) as usize };
+
+        let mut a = BuddyAllocator::new();
+        unsafe {
+            // Only pages 1 and 2 are whole, and they aren't buddies.
+            a.feed_memory(PhysicalAddress((base + 1) as u64), 3 * PAGE_SIZE);
+            assert_eq!(a.alloc(pages(2)), Err(AllocErr));
+            assert_eq!(a.alloc(pages(1)), Ok((base + 2 * PAGE_SIZE) as *mut u8));
+            assert_eq!(a.alloc(pages(1)), Ok((base + PAGE_SIZE) as *mut u8));
+        }
+    }
+
+    #[test]
+    fn region_size_fits_a_block() {
+        static mut BUFFER: Buffer = Buffer([0; 4 * PAGE_SIZE]);
+        let base = unsafe { ptr::addr_of_mut!(BUFFER) as usize };
+
+        let mut a = BuddyAllocator::new();
+        let three = Layout::from_size_align(3 * PAGE_SIZE, 8).unwrap();
+        assert_eq!(a.region_size(&pages(1)), Some(PAGE_SIZE));
+        assert_eq!(a.region_size(&three), Some(7 * PAGE_SIZE));
+        assert_eq!(a.region_size(&pages(2)), Some(3 * PAGE_SIZE));
+
+        unsafe {
+            // The worst case: the region starts one page past a block boundary.
+            a.feed_memory(PhysicalAddress((base + PAGE_SIZE) as u64), 3 * PAGE_SIZE);
+            assert_eq!(a.alloc(pages(2)), Ok((base + 2 * PAGE_SIZE) as *mut u8));
+        }
+    }
+
+    #[test]
+    fn take_free_pages() {
+        static mut BUFFER: Buffer = Buffer([0; 4 * PAGE_SIZE]);
+        let base = unsafe { ptr::addr_of_mut!(BUFFER) as usize };
+
+        let mut a = BuddyAllocator::new();
+        unsafe {
+            a.feed_memory(PhysicalAddress(base as u64), 4 * PAGE_SIZE);
+            let p = a.alloc(pages(1)).unwrap();
+            assert!(a.take_free_pages(4).is_none());
+
+            let (addr, n) = a.take_free_pages(2).unwrap();
+            assert_eq!((addr.0 as usize, n), (base + 2 * PAGE_SIZE, 2));
+
+            a.dealloc(p, pages(1));
+            let (addr, n) = a.take_free_pages(2).unwrap();
+            assert_eq!((addr.0 as usize, n), (base, 2));
+            assert!(a.take_free_pages(1).is_none());
+        }
+    }
+}
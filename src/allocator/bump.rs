@@ -0,0 +1,188 @@
+use core::alloc::Layout;
+use allocator::{AllocErr, FrontAllocator, PhysicalAddress};
+use globals;
+
+/// The simplest possible front allocator, suitable for early boot.
+///
+/// Memory is handed out sequentially from the most recently fed region.
+/// Freed memory is only reused if it was the last allocation made.
+/// When a new region is fed, the rest of the current one is abandoned.
+pub struct BumpAllocator {
+    next: usize,
+    end: usize,
+    last: usize,
+}
+
+impl BumpAllocator {
+    pub const fn new() -> Self {
+        BumpAllocator{next: 0, end: 0, last: 0}
+    }
+
+    /// Number of bytes left in the current region.
+    pub fn remaining(&self) -> usize {
+        self.end - self.next
+    }
+}
+
+unsafe impl FrontAllocator for BumpAllocator {
+    unsafe fn alloc(&mut self, layout: Layout) -> Result<*mut u8, AllocErr> {
+        let start = match self.next.checked_add(layout.align() - 1) {
+            Some(n) => n & !(layout.align() - 1),
+            None => return Err(AllocErr),
+        };
+
+        match start.checked_add(layout.size()) {
+            Some(end) if end <= self.end => {
+                self.last = self.next;
+                self.next = end;
+                Ok(start as *mut u8)
+            },
+            _ => Err(AllocErr),
+        }
+    }
+
+    unsafe fn dealloc(&mut self, ptr: *mut u8, layout: Layout) {
+        // Roll back if this is the most recent allocation.
+        if (ptr as usize) + layout.size() == self.next {
+            self.next = self.last;
+        }
+    }
+
+    unsafe fn feed_memory(&mut self, addr: PhysicalAddress, size: usize) {
+        // A region wrapping around the address space can't be real.
+        let end = match (addr.0 as usize).checked_add(size) {
+            Some(end) => end,
+            None => return,
+        };
+
+        self.next = addr.0 as usize;
+        self.end = end;
+        self.last = self.next;
+    }
+
+    unsafe fn take_free_pages(&mut self, min_pages: usize) -> Option<(PhysicalAddress, usize)> {
+        // Only whole pages at the end of the current region can be given back.
+        let page_mask = globals::PAGE_SIZE - 1;
+        let start = (self.next + page_mask) & !page_mask;
+        let end = self.end & !page_mask;
+
+        if end <= start || (end - start) / globals::PAGE_SIZE < min_pages {
+            return None;
+        }
+
+        self.end = start;
+        Some((PhysicalAddress(start as u64), (end - start) / globals::PAGE_SIZE))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::alloc::Layout;
+    use core::ptr;
+    use allocator::{AllocErr, FrontAllocator, PhysicalAddress};
+    use globals::PAGE_SIZE;
+    use super::BumpAllocator;
+
+    #[repr(align(4096))]
+    struct Buffer([u8; 4 * PAGE_SIZE]);
+
+    fn layout(size: usize, align: usize) -> Layout {
+        Layout::from_size_align(size, align).unwrap()
+    }
+
+    #[test]
+    fn exhaustion() {
+        static mut BUFFER: Buffer = Buffer([0; 4 * PAGE_SIZE]);
+        let base = unsafe { ptr::addr_of_mut!(BUFFER) as usize };
+
+        let mut a = BumpAllocator::new();
+        unsafe {
+            assert_eq!(a.alloc(layout(1, 1)), Err(AllocErr));
+
+            a.feed_memory(PhysicalAddress(base as u64), 64);
+            assert_eq!(a.alloc(layout(32, 8)), Ok(base as *mut u8));
+            assert_eq!(a.alloc(layout(32, 8)), Ok((base + 32) as *mut u8));
+            assert_eq!(a.remaining(), 0);
+            assert_eq!(a.alloc(layout(1, 1)), Err(AllocErr));
+        }
+    }
+
+    #[test]
+    fn alignment_and_rollback() {
+        static mut BUFFER: Buffer = Buffer([0; 4 * PAGE_SIZE]);
+        let base = unsafe { ptr::addr_of_mut!(BUFFER) as usize };
+
+        let mut a = BumpAllocator::new();
+        unsafe {
+            a.feed_memory(PhysicalAddress(base as u64), PAGE_SIZE);
+            let first = a.alloc(layout(3, 1)).unwrap();
+            let second = a.alloc(layout(16, 16)).unwrap();
+            assert_eq!(first as usize, base);
+            assert_eq!(second as usize, base + 16);
+
+            // Only the most recent allocation is rolled back.
+            a.dealloc(first, layout(3, 1));
+            assert_eq!(a.remaining(), PAGE_SIZE - 32);
+            a.dealloc(second, layout(16, 16));
+            assert_eq!(a.remaining(), PAGE_SIZE - 3);
+        }
+    }
+
+    #[test]
+    fn feed_memory_switches_region() {
+        static mut BUFFER: Buffer = Buffer([0; 4 * PAGE_SIZE]);
+        let base = unsafe { ptr::addr_of_mut!(BUFFER) as usize };
+
+        let mut a = BumpAllocator::new();
+        unsafe {
+            a.feed_memory(PhysicalAddress(base as u64), 2 * PAGE_SIZE);
+            a.alloc(layout(10, 1)).unwrap();
+
+            // Even a smaller region replaces the current one.
+            a.feed_memory(PhysicalAddress((base + 2 * PAGE_SIZE) as u64), PAGE_SIZE);
+            assert_eq!(a.remaining(), PAGE_SIZE);
+            assert_eq!(a.alloc(layout(10, 1)), Ok((base + 2 * PAGE_SIZE) as *mut u8));
+
+            // Regions wrapping around the address space are ignored.
+            a.feed_memory(PhysicalAddress(!0), 4 * PAGE_SIZE);
+            assert_eq!(a.remaining(), PAGE_SIZE - 10);
+        }
+    }
+
+    #[test]
+    fn region_size_covers_alignment() {
+        static mut BUFFER: Buffer = Buffer([0; 4 * PAGE_SIZE]);
+        let base = unsafe { ptr::addr_of_mut!(BUFFER) as usize };
+
+        let mut a = BumpAllocator::new();
+        assert_eq!(a.region_size(&layout(0, 1)), Some(1));
+        assert_eq!(a.region_size(&layout(PAGE_SIZE, PAGE_SIZE)), Some(PAGE_SIZE));
+        assert_eq!(a.region_size(&layout(PAGE_SIZE + 1, 8)), Some(PAGE_SIZE + 8));
+        assert_eq!(a.region_size(&layout(8, 2 * PAGE_SIZE)), Some(2 * PAGE_SIZE + 7));
+
+        unsafe {
+            let size = a.region_size(&layout(PAGE_SIZE, 2 * PAGE_SIZE)).unwrap();
+            a.feed_memory(PhysicalAddress(base as u64), size);
+            let p = a.alloc(layout(PAGE_SIZE, 2 * PAGE_SIZE)).unwrap();
+            assert_eq!(p as usize % (2 * PAGE_SIZE), 0);
+        }
+    }
+
+    #[test]
+    fn take_free_pages() {
+        static mut BUFFER: Buffer = Buffer([0; 4 * PAGE_SIZE]);
+        let base = unsafe { ptr::addr_of_mut!(BUFFER) as usize };
+
+        let mut a = BumpAllocator::new();
+        unsafe {
+            a.feed_memory(PhysicalAddress(base as u64), 4 * PAGE_SIZE);
+            a.alloc(layout(10, 1)).unwrap();
+
+            assert!(a.take_free_pages(4).is_none());
+            let (addr, pages) = a.take_free_pages(3).unwrap();
+            assert_eq!((addr.0 as usize, pages), (base + PAGE_SIZE, 3));
+            assert_eq!(a.remaining(), PAGE_SIZE - 10);
+            assert!(a.take_free_pages(1).is_none());
+        }
+    }
+}
@@ -0,0 +1,264 @@
+use core::alloc::Layout;
+use allocator::{AllocErr, FrontAllocator, PhysicalAddress};
+use core::mem;
+use core::ptr;
+use globals;
+
+struct Node {
+    size: usize,
+    next: *mut Node,
+}
+
+// Two words, which is a power of two. Keeping every block address and size
+// a multiple of this guarantees that any leftover space can hold a node.
+const NODE_SIZE: usize = mem::size_of::<Node>();
+
+#[inline]
+fn align_up(addr: usize, align: usize) -> usize {
+    (addr + align - 1) & !(align - 1)
+}
+
+/// General purpose front allocator keeping a list of free blocks.
+///
+/// The list is sorted by address, and adjacent free blocks are merged
+/// when memory is returned, so fragmentation stays bounded.
+/// Allocation is first-fit.
+pub struct FreeListAllocator {
+    head: *mut Node,
+}
+
+impl FreeListAllocator {
+    pub const fn new() -> Self {
+        FreeListAllocator{head: ptr::null_mut()}
+    }
+
+    /// Total number of free bytes.
+    pub fn free_bytes(&self) -> usize {
+        let mut total = 0;
+        let mut node = self.head;
+        while !node.is_null() {
+            unsafe {
+                total += (*node).size;
+                node = (*node).next;
+            }
+        }
+        total
+    }
+
+    #[inline]
+    fn block_size(layout: &Layout) -> usize {
+        if layout.size() == 0 {
+            return NODE_SIZE;
+        }
+        align_up(layout.size(), NODE_SIZE)
+    }
+
+    /// Inserts a free block into the list, merging it with its neighbours.
+    unsafe fn insert(&mut self, addr: usize, size: usize) {
+        let mut prev: *mut Node = ptr::null_mut();
+        let mut next = self.head;
+        while !next.is_null() && (next as usize) < addr {
+            prev = next;
+            next = (*next).next;
+        }
+
+        let node = addr as *mut Node;
+        ptr::write(node, Node{size: size, next: next});
+
+        if !next.is_null() && addr + size == next as usize {
+            (*node).size += (*next).size;
+            (*node).next = (*next).next;
+        }
+
+        if prev.is_null() {
+            self.head = node;
+        } else if (prev as usize) + (*prev).size == addr {
+            (*prev).size += (*node).size;
+            (*prev).next = (*node).next;
+        } else {
+            (*prev).next = node;
+        }
+    }
+}
+
+unsafe impl FrontAllocator for FreeListAllocator {
+    unsafe fn alloc(&mut self, layout: Layout) -> Result<*mut u8, AllocErr> {
+        let size = Self::block_size(&layout);
+        let align = if layout.align() > NODE_SIZE { layout.align() } else { NODE_SIZE };
+
+        let mut prev: *mut Node = ptr::null_mut();
+        let mut node = self.head;
+
+        while !node.is_null() {
+            let block_start = node as usize;
+            let block_end = block_start + (*node).size;
+            let start = align_up(block_start, align);
+
+            if start + size <= block_end {
+                let next = (*node).next;
+
+                // Unlink the block, then give back whatever is left on either side.
+                if prev.is_null() {
+                    self.head = next;
+                } else {
+                    (*prev).next = next;
+                }
+
+                let end = start + size;
+                if start != block_start {
+                    self.insert(block_start, start - block_start);
+                }
+                if end != block_end {
+                    self.insert(end, block_end - end);
+                }
+
+                return Ok(start as *mut u8);
+            }
+
+            prev = node;
+            node = (*node).next;
+        }
+
+        Err(AllocErr)
+    }
+
+    unsafe fn dealloc(&mut self, ptr: *mut u8, layout: Layout) {
+        self.insert(ptr as usize, Self::block_size(&layout));
+    }
+
+    unsafe fn feed_memory(&mut self, addr: PhysicalAddress, size: usize) {
+        let end = match (addr.0 as usize).checked_add(size) {
+            Some(end) => end & !(NODE_SIZE - 1),
+            None => return,
+        };
+        let start = align_up(addr.0 as usize, NODE_SIZE);
+        if end > start {
+            self.insert(start, end - start);
+        }
+    }
+
+    unsafe fn take_free_pages(&mut self, min_pages: usize) -> Option<(PhysicalAddress, usize)> {
+        let mut prev: *mut Node = ptr::null_mut();
+        let mut node = self.head;
+
+        while !node.is_null() {
+            let block_start = node as usize;
+            let block_end = block_start + (*node).size;
+            let start = align_up(block_start, globals::PAGE_SIZE);
+            let end = block_end & !(globals::PAGE_SIZE - 1);
+
+            if end > start && (end - start) / globals::PAGE_SIZE >= min_pages {
+                let next = (*node).next;
+                if prev.is_null() {
+                    self.head = next;
+                } else {
+                    (*prev).next = next;
+                }
+
+                if start != block_start {
+                    self.insert(block_start, start - block_start);
+                }
+                if end != block_end {
+                    self.insert(end, block_end - end);
+                }
+
+                return Some((PhysicalAddress(start as u64), (end - start) / globals::PAGE_SIZE));
+            }
+
+            prev = node;
+            node = (*node).next;
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::alloc::Layout;
+    use core::ptr;
+    use allocator::{AllocErr, FrontAllocator, PhysicalAddress};
+    use globals::PAGE_SIZE;
+    use super::FreeListAllocator;
+
+    #[repr(align(4096))]
+    struct Buffer([u8; 4 * PAGE_SIZE]);
+
+    fn layout(size: usize, align: usize) -> Layout {
+        Layout::from_size_align(size, align).unwrap()
+    }
+
+    #[test]
+    fn split_and_coalesce() {
+        static mut BUFFER: Buffer = Buffer([0; 4 * PAGE_SIZE]);
+        let base = unsafe { ptr::addr_of_mut!(BUFFER) as usize };
+
+        let mut a = FreeListAllocator::new();
+        unsafe {
+            a.feed_memory(PhysicalAddress(base as u64), 1024);
+            let x = a.alloc(layout(64, 8)).unwrap();
+            let y = a.alloc(layout(64, 8)).unwrap();
+            let z = a.alloc(layout(64, 8)).unwrap();
+            assert_eq!((x as usize, y as usize, z as usize), (base, base + 64, base + 128));
+            assert_eq!(a.free_bytes(), 1024 - 192);
+
+            // A hole that is too small is skipped, and reused when it fits.
+            a.dealloc(y, layout(64, 8));
+            assert_eq!(a.alloc(layout(128, 8)), Ok((base + 192) as *mut u8));
+            assert_eq!(a.alloc(layout(64, 8)), Ok(y));
+            a.dealloc(y, layout(64, 8));
+            a.dealloc((base + 192) as *mut u8, layout(128, 8));
+
+            // Freeing both neighbours merges everything back into one block.
+            a.dealloc(x, layout(64, 8));
+            a.dealloc(z, layout(64, 8));
+            assert_eq!(a.free_bytes(), 1024);
+            assert_eq!(a.alloc(layout(1024, 8)), Ok(base as *mut u8));
+            assert_eq!(a.alloc(layout(1, 1)), Err(AllocErr));
+        }
+    }
+
+    #[test]
+    fn alignment() {
+        static mut BUFFER: Buffer = Buffer([0; 4 * PAGE_SIZE]);
+        let base = unsafe { ptr::addr_of_mut!(BUFFER) as usize };
+
+        let mut a = FreeListAllocator::new();
+        unsafe {
+            // Unaligned regions are trimmed to whole nodes.
+            a.feed_memory(PhysicalAddress((base + 3) as u64), 1024);
+            assert_eq!(a.free_bytes(), 1024 - 16);
+
+            let p = a.alloc(layout(100, 256)).unwrap();
+            assert_eq!(p as usize, base + 256);
+
+            // The space skipped for alignment stays available.
+            assert_eq!(a.free_bytes(), 1024 - 16 - 112);
+            assert_eq!(a.alloc(layout(240, 8)), Ok((base + 16) as *mut u8));
+
+            // Zero-sized and small allocations take up a whole node.
+            let q = a.alloc(layout(0, 1)).unwrap();
+            let r = a.alloc(layout(1, 1)).unwrap();
+            assert_eq!(r as usize - q as usize, 16);
+        }
+    }
+
+    #[test]
+    fn take_free_pages() {
+        static mut BUFFER: Buffer = Buffer([0; 4 * PAGE_SIZE]);
+        let base = unsafe { ptr::addr_of_mut!(BUFFER) as usize };
+
+        let mut a = FreeListAllocator::new();
+        unsafe {
+            a.feed_memory(PhysicalAddress((base + 64) as u64), 4 * PAGE_SIZE - 128);
+            assert!(a.take_free_pages(3).is_none());
+
+            let (addr, pages) = a.take_free_pages(2).unwrap();
+            assert_eq!((addr.0 as usize, pages), (base + PAGE_SIZE, 2));
+
+            // The partial pages on either side are kept.
+            assert_eq!(a.free_bytes(), 2 * PAGE_SIZE - 128);
+            assert!(a.take_free_pages(1).is_none());
+        }
+    }
+}
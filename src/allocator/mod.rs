@@ -0,0 +1,317 @@
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::UnsafeCell;
+use core::cmp;
+use core::mem;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+use globals;
+use protocol::tpl;
+use protocol::boot_services::{AllocateType, MemoryAttribute, MemoryType};
+use core::usize;
+
+mod buddy;
+mod bump;
+mod free_list;
+
+pub use self::buddy::BuddyAllocator;
+pub use self::bump::BumpAllocator;
+pub use self::free_list::FreeListAllocator;
+
+pub struct PhysicalAddress(pub u64);
+
+/// Error returned when an allocator can't satisfy a request.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct AllocErr;
+
+pub unsafe trait FrontAllocator {
+    unsafe fn alloc(&mut self, layout: Layout) -> Result<*mut u8, AllocErr>;
+    unsafe fn dealloc(&mut self, ptr: *mut u8, layout: Layout);
+    unsafe fn feed_memory(&mut self, addr: PhysicalAddress, size: usize);
+
+    /// Removes a run of at least `min_pages` whole free pages from the allocator,
+    /// returning its address and length in pages.
+    ///
+    /// This lets `Allocator` return unused memory to the firmware.
+    /// The default implementation never gives anything back.
+    unsafe fn take_free_pages(&mut self, _min_pages: usize) -> Option<(PhysicalAddress, usize)> {
+        None
+    }
+
+    /// Size of a page-aligned region that, once fed, is sure to satisfy `layout`.
+    ///
+    /// The default is enough for allocators that can place a block anywhere
+    /// in a region, as long as it's suitably aligned.
+    fn region_size(&self, layout: &Layout) -> Option<usize> {
+        let size = cmp::max(layout.size(), 1);
+        if layout.align() > globals::PAGE_SIZE || size > globals::PAGE_SIZE {
+            size.checked_add(layout.align() - 1)
+        } else {
+            Some(size)
+        }
+    }
+}
+
+pub struct Allocator<F: FrontAllocator> {
+    fully_stocked: bool,
+    alloc: F,
+}
+
+/// Smallest run of free pages worth returning to the firmware.
+/// Smaller runs are kept to avoid calling into the firmware on every deallocation.
+const RELEASE_MIN_PAGES: usize = 16;
+
+impl<F: FrontAllocator> Allocator<F> {
+    /// Note that free page runs are returned to the firmware while boot services
+    /// are active, so `alloc` must not already hold memory that wasn't obtained
+    /// with `AllocatePages()`.
+    pub const fn new(alloc: F) -> Self {
+        Allocator{fully_stocked: false, alloc: alloc}
+    }
+
+    #[cold]
+    #[inline(never)]
+    unsafe fn restock(&mut self, layout: Layout) {
+        if self.fully_stocked {
+            return;
+        }
+
+        let size = match self.alloc.region_size(&layout) {
+            Some(size) => size,
+            None => return,
+        };
+
+        if let Some(table) = globals::BOOT_SERVICES_TABLE {
+            // Allocate missing memory from EFI boot services.
+
+            let pages = ((size - 1) / globals::PAGE_SIZE) + 1;
+            if let Ok(addr) = table.allocate_pages(AllocateType::AllocateAnyPages, MemoryType::LoaderData, pages, 0) {
+                self.alloc.feed_memory(PhysicalAddress(addr as _), pages * globals::PAGE_SIZE);
+            }
+        } else {
+            // We have already exited boot services.
+            // Take free memory from the final memory map.
+
+            if let Some((addr, size)) = claim_from_memory_map(size) {
+                self.alloc.feed_memory(addr, size);
+            } else if globals::RECLAIM_BOOT_SERVICES_MEMORY {
+                // Nothing big enough left, and nothing more will become available.
+                self.fully_stocked = true;
+            }
+        }
+    }
+
+    /// Returns runs of free pages to the firmware.
+    ///
+    /// Does nothing once boot services have been exited,
+    /// as all memory is then owned by the application anyway.
+    #[cold]
+    #[inline(never)]
+    unsafe fn release(&mut self) {
+        let table = match globals::BOOT_SERVICES_TABLE {
+            Some(table) => table,
+            None => return,
+        };
+
+        while let Some((addr, pages)) = self.alloc.take_free_pages(RELEASE_MIN_PAGES) {
+            if table.free_pages(addr.0 as _, pages).is_err() {
+                // Keep what the firmware doesn't want back.
+                self.alloc.feed_memory(addr, pages * globals::PAGE_SIZE);
+                break;
+            }
+        }
+    }
+}
+
+/// Finds a region in the final memory map that is free for the loader to use
+/// and is at least `size` bytes big.
+///
+/// The whole region is retyped as `LoaderData` in the map, so that it's never
+/// handed out twice and the kernel sees it as used.
+unsafe fn claim_from_memory_map(size: usize) -> Option<(PhysicalAddress, usize)> {
+    let map = match globals::MEMORY_MAP {
+        Some(ref mut map) => map,
+        None => return None,
+    };
+
+    let mut found = None;
+
+    for (i, desc) in map.descriptors().enumerate() {
+        let reclaimable = match desc.memory_type() {
+            Some(MemoryType::ConventionalMemory) => true,
+            Some(MemoryType::BootServicesCode) |
+            Some(MemoryType::BootServicesData) => globals::RECLAIM_BOOT_SERVICES_MEMORY,
+            _ => false,
+        };
+
+        // Runtime services may still need anything marked as such.
+        if !reclaimable || desc.attribute.contains(MemoryAttribute::RUNTIME) {
+            continue;
+        }
+
+        // Never hand out page zero, it would be indistinguishable from a null pointer.
+        let mut start = desc.physical_start;
+        let mut len = desc.size();
+        if start == 0 {
+            start += globals::PAGE_SIZE as u64;
+            len = len.saturating_sub(globals::PAGE_SIZE as u64);
+        }
+
+        // Skip memory that is not addressable on this target.
+        if len == 0 || start + len - 1 > usize::MAX as u64 {
+            continue;
+        }
+
+        if len >= size as u64 {
+            found = Some((i, start, len));
+            break;
+        }
+    }
+
+    found.map(|(i, start, len)| {
+        map.set_type(i, MemoryType::LoaderData);
+        (PhysicalAddress(start), len as usize)
+    })
+}
+
+
+impl<F: FrontAllocator> Allocator<F> {
+    /// Allocates memory from the front allocator, restocking it if needed.
+    #[inline]
+    pub unsafe fn alloc(&mut self, layout: Layout) -> Result<*mut u8, AllocErr> {
+        if let Ok(p) = self.alloc.alloc(layout) {
+            return Ok(p);
+        }
+
+        self.restock(layout);
+        self.alloc.alloc(layout)
+    }
+
+    /// Returns memory to the front allocator, and free page runs to the firmware.
+    #[inline]
+    pub unsafe fn dealloc(&mut self, ptr: *mut u8, layout: Layout) {
+        let size = layout.size();
+        self.alloc.dealloc(ptr, layout);
+
+        if size >= globals::PAGE_SIZE {
+            self.release();
+        }
+    }
+}
+
+
+/// Wrapper that makes `Allocator` usable as the `#[global_allocator]`.
+///
+/// ```ignore
+/// #[global_allocator]
+/// static ALLOCATOR: GlobalAllocator<MyHeap> = GlobalAllocator::new(Allocator::new(MyHeap::new()));
+/// ```
+///
+/// UEFI applications run on a single processor, so the lock only guards against
+/// re-entrant calls. The task priority level is raised to `Tpl::NOTIFY` while
+/// the allocator is in use, so only notification functions running above that
+/// level could still get in. Instead of deadlocking, such an allocation fails
+/// and returns null, and such a deallocation is deferred until the allocator
+/// is next used.
+pub struct GlobalAllocator<F: FrontAllocator> {
+    locked: AtomicBool,
+    deferred: AtomicPtr<Deferred>,
+    inner: UnsafeCell<Allocator<F>>,
+}
+
+/// A block whose deallocation was deferred, stored in the block itself.
+struct Deferred {
+    next: *mut Deferred,
+    layout: Layout,
+}
+
+unsafe impl<F: FrontAllocator> Sync for GlobalAllocator<F> {}
+
+impl<F: FrontAllocator> GlobalAllocator<F> {
+    pub const fn new(alloc: Allocator<F>) -> Self {
+        GlobalAllocator {
+            locked: AtomicBool::new(false),
+            deferred: AtomicPtr::new(ptr::null_mut()),
+            inner: UnsafeCell::new(alloc),
+        }
+    }
+
+    /// The layout actually allocated for `layout`,
+    /// big enough to hold a `Deferred` in case the deallocation is deferred.
+    #[inline]
+    fn padded(layout: Layout) -> Option<Layout> {
+        let size = cmp::max(layout.size(), mem::size_of::<Deferred>());
+        let align = cmp::max(layout.align(), mem::align_of::<Deferred>());
+        Layout::from_size_align(size, align).ok()
+    }
+
+    /// Runs `f` with exclusive access to the inner allocator,
+    /// or returns `None` if the allocator is already in use.
+    ///
+    /// While boot services are active, the task priority level is raised
+    /// so that event notification functions can't interrupt `f`.
+    #[inline]
+    fn with_lock<R, G: FnOnce(&mut Allocator<F>) -> R>(&self, f: G) -> Option<R> {
+        let _tpl = tpl::protect_global_state();
+
+        if self.locked.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            return None;
+        }
+
+        let inner = unsafe { &mut *self.inner.get() };
+        let result = f(inner);
+        unsafe { self.free_deferred(inner) };
+
+        self.locked.store(false, Ordering::Release);
+        Some(result)
+    }
+
+    /// Frees the blocks whose deallocation was deferred.
+    #[cold]
+    unsafe fn free_deferred(&self, inner: &mut Allocator<F>) {
+        let mut p = self.deferred.swap(ptr::null_mut(), Ordering::Acquire);
+        while !p.is_null() {
+            let Deferred{ next, layout } = ptr::read(p);
+            inner.dealloc(p as *mut u8, layout);
+            p = next;
+        }
+    }
+
+    /// Queues a block to be freed once the allocator is no longer in use.
+    unsafe fn defer_dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let node = ptr as *mut Deferred;
+        let mut head = self.deferred.load(Ordering::Relaxed);
+        loop {
+            ptr::write(node, Deferred{ next: head, layout: layout });
+            match self.deferred.compare_exchange_weak(head, node, Ordering::Release, Ordering::Relaxed) {
+                Ok(_) => break,
+                Err(current) => head = current,
+            }
+        }
+    }
+}
+
+unsafe impl<F: FrontAllocator> GlobalAlloc for GlobalAllocator<F> {
+    #[inline]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let layout = match Self::padded(layout) {
+            Some(layout) => layout,
+            None => return ptr::null_mut(),
+        };
+
+        match self.with_lock(|a| a.alloc(layout)) {
+            Some(Ok(p)) => p,
+            _ => ptr::null_mut(),
+        }
+    }
+
+    #[inline]
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // Padding succeeded when the block was allocated.
+        let layout = Self::padded(layout).unwrap();
+
+        if self.with_lock(|a| a.dealloc(ptr, layout)).is_none() {
+            self.defer_dealloc(ptr, layout);
+        }
+    }
+}
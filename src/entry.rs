@@ -0,0 +1,92 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use {Arg1, Arg2, BootContext};
+use protocol::{Error, Status};
+
+#[doc(hidden)]
+pub use efi_types::{EFI_HANDLE, EFI_STATUS, EFI_SYSTEM_TABLE};
+
+/// Declares `efi_main`, the entry point of the application.
+///
+/// The given function receives the `BootContext`, and its return value
+/// is converted into the `EFI_STATUS` returned to the firmware.
+///
+/// ```ignore
+/// #[macro_use]
+/// extern crate efi_app;
+///
+/// declare_entry!(main);
+///
+/// fn main(ctx: efi_app::BootContext) -> Result<(), efi_app::Error> {
+///     println!("Hello, world!");
+///     Ok(())
+/// }
+/// ```
+#[macro_export]
+macro_rules! declare_entry {
+    ($main:path) => {
+        #[cfg(target_arch = "x86_64")]
+        #[no_mangle]
+        pub extern "win64" fn efi_main(image_handle: $crate::entry::EFI_HANDLE,
+                                       system_table: *mut $crate::entry::EFI_SYSTEM_TABLE)
+                                       -> $crate::entry::EFI_STATUS {
+            unsafe { $crate::entry::run(image_handle, system_table, $main) }
+        }
+
+        #[cfg(not(target_arch = "x86_64"))]
+        #[no_mangle]
+        pub extern "C" fn efi_main(image_handle: $crate::entry::EFI_HANDLE,
+                                   system_table: *mut $crate::entry::EFI_SYSTEM_TABLE)
+                                   -> $crate::entry::EFI_STATUS {
+            unsafe { $crate::entry::run(image_handle, system_table, $main) }
+        }
+    };
+}
+
+/// Return types accepted from the function passed to `declare_entry!`.
+pub trait IntoStatus {
+    fn into_status(self) -> EFI_STATUS;
+}
+
+impl IntoStatus for () {
+    fn into_status(self) -> EFI_STATUS {
+        Status::SUCCESS.into_raw()
+    }
+}
+
+impl IntoStatus for Status {
+    fn into_status(self) -> EFI_STATUS {
+        self.into_raw()
+    }
+}
+
+impl IntoStatus for Error {
+    fn into_status(self) -> EFI_STATUS {
+        self.into_raw()
+    }
+}
+
+impl<T: IntoStatus> IntoStatus for Result<T, Error> {
+    fn into_status(self) -> EFI_STATUS {
+        match self {
+            Ok(v) => v.into_status(),
+            Err(e) => e.into_status(),
+        }
+    }
+}
+
+static ENTERED: AtomicBool = AtomicBool::new(false);
+
+/// Called by the `efi_main` generated by `declare_entry!`.
+#[doc(hidden)]
+pub unsafe fn run<R, F>(image_handle: EFI_HANDLE, system_table: *mut EFI_SYSTEM_TABLE, main: F) -> EFI_STATUS
+    where R: IntoStatus, F: FnOnce(BootContext) -> R
+{
+    // There must only ever be one `BootContext`.
+    if ENTERED.swap(true, Ordering::SeqCst) {
+        return Error::ALREADY_STARTED.into_raw();
+    }
+
+    let ctx = BootContext::new(Arg1(image_handle), Arg2(system_table));
+    main(ctx).into_status()
+}
@@ -1,21 +1,33 @@
-#![feature(alloc)]
-#![feature(allocator_api)]
-#![feature(const_fn)]
-#![feature(try_from)]
-
 #![no_std]
 
 #![allow(dead_code)]
 
 
 extern crate efi_types;
-
+extern crate alloc;
+#[cfg(feature = "log")]
+extern crate log;
+
+#[macro_use]
+pub mod print;
+#[doc(hidden)]
+#[macro_use]
+pub mod entry;
 pub mod protocol;
+pub mod string;
+mod abort;
+#[cfg(feature = "log")]
+pub mod logger;
+pub mod ucs2;
+
+pub use string::{CStr16, CString16};
 
-use core::mem;
+pub use abort::{abort, halt};
+pub use protocol::{Error, Status};
 
 mod allocator;
-pub use allocator::{Allocator, FrontAllocator, PhysicalAddress};
+pub use allocator::{AllocErr, Allocator, FrontAllocator, GlobalAllocator, PhysicalAddress};
+pub use allocator::{BuddyAllocator, BumpAllocator, FreeListAllocator};
 
 mod globals {
     use efi_types;
@@ -23,9 +35,16 @@ mod globals {
     use core::ptr;
 
     pub(crate) const PAGE_SIZE: usize = 4096;
+    pub(crate) static mut IMAGE_HANDLE: efi_types::EFI_HANDLE = ptr::null_mut();
     pub(crate) static mut SYSTEM_TABLE: *mut efi_types::EFI_SYSTEM_TABLE = ptr::null_mut();
-    pub(crate) static mut BOOT_SERVICES_TABLE: Option<&mut protocol::boot_services::BootServices> = None;
+    // Only ever shared: `ProtocolRef`s borrow it, so all `BootServices` methods take `&self`.
+    pub(crate) static mut BOOT_SERVICES_TABLE: Option<&'static protocol::boot_services::BootServices> = None;
     pub(crate) static mut RUNTIME_SERVICES_TABLE: *mut efi_types::EFI_RUNTIME_SERVICES = ptr::null_mut();
+
+    // Final memory map, set when boot services are terminated.
+    pub(crate) static mut MEMORY_MAP: Option<protocol::boot_services::MemoryMap> = None;
+    // Whether `BootServicesCode` and `BootServicesData` regions may be reused.
+    pub(crate) static mut RECLAIM_BOOT_SERVICES_MEMORY: bool = false;
 }
 
 //#[repr(transparent)]
@@ -34,90 +53,42 @@ pub struct Arg1(efi_types::EFI_HANDLE);
 //#[repr(transparent)]
 pub struct Arg2(*mut efi_types::EFI_SYSTEM_TABLE);
 
-pub struct Status(efi_types::EFI_STATUS);
-
-impl Status {
-    pub const fn success() -> Self {
-        Status(efi_types::EFI_SUCCESS as efi_types::EFI_STATUS)
-    }
-
-    pub fn load_error() -> Self {
-        let efi_load_error: efi_types::EFI_STATUS = if mem::size_of::<efi_types::EFI_STATUS>() == 4 {
-            0x80000001
-        } else {
-            0x8000000000000001 as efi_types::EFI_STATUS
-        };
-
-        Status(efi_load_error)
-    }
-}
-
-#[derive(Default)]
-struct PBuffer {
-    buffer: [u16; 32],
-}
-
 pub struct BootContext {
-    print_buffer: PBuffer,
-}
-
-pub fn __print(s: &str) {
-    let out = unsafe{__fixme_temporary_out()};
-    out.output_string(s);
-}
-
-pub fn __println(s: &str) {
-    __print(s);
-    __print("\n");
-}
-
-pub fn __printx64(num: u64) {
-    __print("0x");
-    for i in 0..16 {
-        __print(match (num >> ((15-i)*4)) & 0xf {
-            0 => "0",
-            1 => "1",
-            2 => "2",
-            3 => "3",
-            4 => "4",
-            5 => "5",
-            6 => "6",
-            7 => "7",
-            8 => "8",
-            9 => "9",
-            10 => "A",
-            11 => "B",
-            12 => "C",
-            13 => "D",
-            14 => "E",
-            15 => "F",
-            _ => { __println("\n\nUNREACHABLE REACHED\n\n"); unreachable!() },
-        });
-    }
-}
-
-pub fn __printx64ln(num: u64) {
-    __printx64(num);
-    __print("\n");
-}
-
-pub fn __printval(s: &str, num: usize) {
-    __print(s);
-    __print(": ");
-    __printx64ln(num as u64);
+    image_handle: efi_types::EFI_HANDLE,
+    print_buffer: print::PBuffer,
 }
 
-pub unsafe fn __fixme_temporary_out() -> &'static mut protocol::console::simple_text_output::Protocol {
-    &mut *((*globals::SYSTEM_TABLE).ConOut as *mut protocol::console::simple_text_output::Protocol)
+/// Context of an application that has exited boot services.
+///
+/// Only operations that remain valid after `ExitBootServices()` are available.
+pub struct RuntimeContext {
+    _private: (),
 }
 
 impl BootContext {
-    pub unsafe fn new(_image_handle: Arg1, system_table: Arg2) -> BootContext {
+    pub unsafe fn new(image_handle: Arg1, system_table: Arg2) -> BootContext {
+        let Arg1(handle) = image_handle;
         let Arg2(table) = system_table;
+        globals::IMAGE_HANDLE = handle;
         globals::SYSTEM_TABLE = table;
-        globals::BOOT_SERVICES_TABLE = ((*table).BootServices as *mut protocol::boot_services::BootServices).as_mut();
+        globals::BOOT_SERVICES_TABLE = ((*table).BootServices as *const protocol::boot_services::BootServices).as_ref();
         globals::RUNTIME_SERVICES_TABLE = (*table).RuntimeServices;
-        BootContext{ print_buffer: PBuffer::default() }
+        BootContext{ image_handle: handle, print_buffer: print::PBuffer::new() }
+    }
+
+    pub fn boot_services(&self) -> &protocol::boot_services::BootServices {
+        unsafe { globals::BOOT_SERVICES_TABLE.unwrap() }
+    }
+
+    /// Handle of the running image, e.g. to pass as parent to `load_image()`.
+    pub fn image_handle(&self) -> protocol::handle::Handle {
+        unsafe { protocol::handle::Handle::from_raw(self.image_handle) }
+    }
+
+    /// The `LoadedImage` protocol of the running image,
+    /// with its command line and the device it was loaded from.
+    pub fn loaded_image(&self) -> protocol::Result<protocol::handle::ProtocolRef<protocol::loaded_image::LoadedImage>> {
+        self.boot_services().open_protocol(self.image_handle(), protocol::handle::OpenProtocolAttributes::GetProtocol)
     }
 
     pub fn console_out(&mut self) -> &mut protocol::console::simple_text_output::Protocol {
@@ -125,28 +96,83 @@ impl BootContext {
     }
 
     pub fn print(&mut self, s: &str) {
-        core::mem::drop(self.console_out().output_string(s));
+        self.print_fmt(format_args!("{}", s));
     }
 
-/*
-
-    unsafe fn allocate_pages() {
+    /// Prints formatted text like `print!`, staged in the context's own buffer.
+    pub fn print_fmt(&mut self, args: core::fmt::Arguments) {
+        print::print_buffered(&mut self.print_buffer, print::Stream::Out, args);
     }
 
-    unsafe fn free_pages()
-    fn get_memory_map() {
-    }
+    /// Terminates boot services and returns a context for the runtime phase.
+    ///
+    /// The final memory map is fetched right before calling `ExitBootServices()`.
+    /// If the firmware rejects the map key because the map changed in the meantime,
+    /// the map is fetched again into the same buffer and the call is retried.
+    ///
+    /// Panics if boot services can't be terminated.
+    pub fn exit(self) -> RuntimeContext {
+        const MAX_ATTEMPTS: usize = 8;
+
+        let table = unsafe { globals::BOOT_SERVICES_TABLE.unwrap() };
 
+        let mut map = match table.get_memory_map_with_slack(8) {
+            Ok(map) => map,
+            Err(_) => panic!("failed to get memory map"),
+        };
 
+        for _ in 0..MAX_ATTEMPTS {
+            match unsafe { table.exit_boot_services(self.image_handle, map.map_key()) } {
+                Ok(()) => {
+                    unsafe {
+                        globals::BOOT_SERVICES_TABLE = None;
+                        globals::MEMORY_MAP = Some(map);
+                    }
+                    return RuntimeContext{ _private: () };
+                },
+                Err(ref e) if *e == protocol::Error::INVALID_PARAMETER => {
+                    // The map key is stale. Only `GetMemoryMap()` and `ExitBootServices()`
+                    // may be called at this point, so reuse the buffer we already have.
+                    if table.refresh_memory_map(&mut map).is_err() {
+                        break;
+                    }
+                },
+                Err(_) => break,
+            }
+        }
+
+        // Boot services may be partially shut down, so don't call `FreePages()`.
+        core::mem::forget(map);
+        panic!("ExitBootServices() failed")
+    }
 
+/*
     pub fn validate(&self) -> Result<(), ValidateError> {
         unimplemented!()
     }
+*/
 
-    pub fn exit(self) -> RuntimeContext {
-        unimplemented!()
+}
+
+impl RuntimeContext {
+    /// The memory map at the time boot services were terminated.
+    ///
+    /// Regions that have since been handed to the allocator are marked as `LoaderData`.
+    pub fn memory_map(&self) -> &protocol::boot_services::MemoryMap {
+        unsafe { globals::MEMORY_MAP.as_ref().unwrap() }
     }
-*/
 
+    /// Allows the allocator to reuse `BootServicesCode` and `BootServicesData` regions.
+    ///
+    /// Unsafe because the firmware allocates the initial stack as `BootServicesData`.
+    /// Only call this once nothing in those regions is used anymore,
+    /// e.g. after switching to a stack allocated by the loader.
+    pub unsafe fn reclaim_boot_services_memory(&mut self) {
+        globals::RECLAIM_BOOT_SERVICES_MEMORY = true;
+    }
+
+    pub fn runtime_services(&mut self) -> &mut protocol::runtime_services::RuntimeServices {
+        unsafe { &mut *(globals::RUNTIME_SERVICES_TABLE as *mut protocol::runtime_services::RuntimeServices) }
+    }
 }
 
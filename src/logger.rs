@@ -0,0 +1,113 @@
+use core::fmt;
+use core::fmt::Write;
+use log::{self, Level, LevelFilter, Log, Metadata, Record, SetLoggerError};
+
+use globals;
+use print::{self, Sink};
+use protocol::Color;
+use protocol::console::simple_text_output::{Attribute, Protocol};
+
+/// Backend for the `log` crate.
+///
+/// Records are written to `ConOut` with the level in colour, and also to `StdErr`
+/// if it is a different device. Every record is additionally passed to the serial
+/// sink, if one is set. After `ExitBootServices()` the firmware consoles are gone,
+/// so records only go to the serial sink, or to the fallback sink of `print!`.
+pub struct Logger {
+    _private: (),
+}
+
+static LOGGER: Logger = Logger{ _private: () };
+static mut SERIAL_SINK: Option<Sink> = None;
+
+/// Installs the logger, with `max_level` as the initial maximum level.
+///
+/// The level can't be raised beyond the compile-time maximum selected
+/// with the `max_level_*` features of the `log` crate.
+pub fn init(max_level: LevelFilter) -> Result<(), SetLoggerError> {
+    log::set_logger(&LOGGER)?;
+    log::set_max_level(max_level);
+    Ok(())
+}
+
+/// Changes the maximum level at runtime.
+pub fn set_max_level(max_level: LevelFilter) {
+    log::set_max_level(max_level);
+}
+
+/// Sets a sink that receives a copy of every record, e.g. a serial port writer.
+pub fn set_serial_sink(sink: Option<Sink>) {
+    unsafe { SERIAL_SINK = sink; }
+}
+
+fn level_color(level: Level) -> Color {
+    match level {
+        Level::Error => Color::LightRed,
+        Level::Warn => Color::Yellow,
+        Level::Info => Color::LightGreen,
+        Level::Debug => Color::LightCyan,
+        Level::Trace => Color::DarkGray,
+    }
+}
+
+struct SinkWriter(Sink);
+
+impl fmt::Write for SinkWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        (self.0)(s);
+        Ok(())
+    }
+}
+
+fn write_plain<W: fmt::Write>(w: &mut W, record: &Record) {
+    let _ = write!(w, "[{:5}] {}: {}\n", record.level(), record.target(), record.args());
+}
+
+fn write_console(out: &mut Protocol, record: &Record) {
+    let saved = out.mode().attribute;
+
+    let _ = out.output_string("[");
+    let _ = out.set_attribute(Attribute::new(level_color(record.level()), saved.background()));
+    let _ = write!(out, "{:5}", record.level());
+    let _ = out.set_attribute(saved);
+    let _ = write!(out, "] {}: {}\n", record.target(), record.args());
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        unsafe {
+            let serial = SERIAL_SINK;
+
+            if globals::BOOT_SERVICES_TABLE.is_none() {
+                // The consoles are gone. Never touch the firmware again.
+                match serial {
+                    Some(sink) => write_plain(&mut SinkWriter(sink), record),
+                    None => print::_print(print::Stream::Err, format_args!("[{:5}] {}: {}\n",
+                                          record.level(), record.target(), record.args())),
+                }
+                return;
+            }
+
+            let table = &*globals::SYSTEM_TABLE;
+            write_console(&mut *(table.ConOut as *mut Protocol), record);
+
+            if !table.StdErr.is_null() && table.StandardErrorHandle != table.ConsoleOutHandle {
+                write_console(&mut *(table.StdErr as *mut Protocol), record);
+            }
+
+            if let Some(sink) = serial {
+                write_plain(&mut SinkWriter(sink), record);
+            }
+        }
+    }
+
+    fn flush(&self) {}
+}
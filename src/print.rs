@@ -0,0 +1,146 @@
+use core::fmt;
+use core::fmt::Write;
+
+use globals;
+use protocol::console::simple_text_output;
+use ucs2;
+
+/// Prints to the console.
+///
+/// Before `ExitBootServices()`, output goes to `ConOut`.
+/// Afterwards it goes to the sink set with `set_fallback_sink()`, if any.
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => ($crate::print::_print($crate::print::Stream::Out, format_args!($($arg)*)));
+}
+
+/// Prints to the console, with a newline.
+#[macro_export]
+macro_rules! println {
+    () => ($crate::print!("\n"));
+    ($fmt:expr) => ($crate::print!(concat!($fmt, "\n")));
+    ($fmt:expr, $($arg:tt)*) => ($crate::print!(concat!($fmt, "\n"), $($arg)*));
+}
+
+/// Prints to the standard error console.
+///
+/// Before `ExitBootServices()`, output goes to `StdErr`.
+/// Afterwards it goes to the sink set with `set_fallback_sink()`, if any.
+#[macro_export]
+macro_rules! eprint {
+    ($($arg:tt)*) => ($crate::print::_print($crate::print::Stream::Err, format_args!($($arg)*)));
+}
+
+/// Prints to the standard error console, with a newline.
+#[macro_export]
+macro_rules! eprintln {
+    () => ($crate::eprint!("\n"));
+    ($fmt:expr) => ($crate::eprint!(concat!($fmt, "\n")));
+    ($fmt:expr, $($arg:tt)*) => ($crate::eprint!(concat!($fmt, "\n"), $($arg)*));
+}
+
+#[doc(hidden)]
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Stream {
+    Out,
+    Err,
+}
+
+/// Receives printed text once boot services are gone, e.g. to write it to a serial port.
+pub type Sink = fn(&str);
+
+static mut FALLBACK_SINK: Option<Sink> = None;
+
+/// Sets where `print!` and friends write after `ExitBootServices()`.
+///
+/// Without a sink, output after exit is discarded.
+pub fn set_fallback_sink(sink: Option<Sink>) {
+    unsafe { FALLBACK_SINK = sink; }
+}
+
+/// Staging buffer for console output, so that formatting never needs to allocate
+/// and the firmware is called once per buffer rather than once per fragment.
+///
+/// `BootContext` owns one, and `print!` and friends use one on the stack.
+pub(crate) struct PBuffer {
+    buffer: [u16; 128],
+    len: usize,
+}
+
+fn console(stream: Stream) -> &'static mut simple_text_output::Protocol {
+    unsafe {
+        let table = &*globals::SYSTEM_TABLE;
+        let p = match stream {
+            Stream::Out => table.ConOut,
+            Stream::Err if table.StdErr.is_null() => table.ConOut,
+            Stream::Err => table.StdErr,
+        };
+        &mut *(p as *mut simple_text_output::Protocol)
+    }
+}
+
+impl PBuffer {
+    pub(crate) fn new() -> PBuffer {
+        PBuffer{ buffer: [0; 128], len: 0 }
+    }
+
+    fn flush(&mut self, stream: Stream) {
+        if self.len == 0 {
+            return;
+        }
+        self.buffer[self.len] = 0;
+        let _ = console(stream).output_string_utf16(&self.buffer[..self.len+1]);
+        self.len = 0;
+    }
+
+    fn write(&mut self, stream: Stream, s: &str) {
+        let encoder = ucs2::Encoder::new(s, ucs2::Policy::Replace).expand_newlines(true);
+        for unit in encoder {
+            // Keep room for the terminator, and don't split "\r\n" between calls.
+            let needed = if unit == '\r' as u16 { 2 } else { 1 };
+            if self.len + needed >= self.buffer.len() {
+                self.flush(stream);
+            }
+            self.buffer[self.len] = unit;
+            self.len += 1;
+        }
+    }
+}
+
+struct Writer<'a> {
+    stream: Stream,
+    buffer: &'a mut PBuffer,
+}
+
+impl<'a> fmt::Write for Writer<'a> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        unsafe {
+            if globals::BOOT_SERVICES_TABLE.is_none() {
+                if let Some(sink) = FALLBACK_SINK {
+                    sink(s);
+                }
+            } else {
+                self.buffer.write(self.stream, s);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Formats `args` through `buffer`, flushing it at the end.
+pub(crate) fn print_buffered(buffer: &mut PBuffer, stream: Stream, args: fmt::Arguments) {
+    {
+        let mut writer = Writer{ stream: stream, buffer: buffer };
+        let _ = writer.write_fmt(args);
+    }
+    if unsafe { globals::BOOT_SERVICES_TABLE.is_some() } {
+        buffer.flush(stream);
+    }
+}
+
+#[doc(hidden)]
+pub fn _print(stream: Stream, args: fmt::Arguments) {
+    // A buffer of our own, so nested calls from notification functions don't need a lock.
+    let mut buffer = PBuffer::new();
+    print_buffered(&mut buffer, stream, args);
+}
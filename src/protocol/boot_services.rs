@@ -1,6 +1,25 @@
 use efi_types;
+use core;
+use core::cmp;
+use core::convert::TryFrom;
+use core::mem;
+use core::ops::BitOr;
+use core::ptr;
 
-use protocol::{Result, status_to_result};
+extern crate alloc;
+use self::alloc::boxed::Box;
+
+use globals;
+use protocol::{Error, Guid, Protocol, Result, Status, status_to_result, status_to_status};
+use protocol::event::{self, Event, EventType, Notify, Timer};
+use protocol::image::{ExitData, Image, ImageExit, ImageSource};
+use protocol::loaded_image::LoadedImage;
+use protocol::handle::{Handle, OpenProtocolAttributes, ProtocolGuids, ProtocolRef, Registration, SearchType};
+use protocol::pool::PoolSlice;
+use protocol::tpl::{Tpl, TplGuard};
+
+/// Most events `BootServices::wait_for_event()` can wait for at once.
+pub const MAX_WAIT_EVENTS: usize = 16;
 
 #[repr(C)]
 pub enum AllocateType {
@@ -10,6 +29,7 @@ pub enum AllocateType {
 }
 
 #[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum MemoryType {
     ReservedMemoryType,
     LoaderCode,
@@ -29,18 +49,903 @@ pub enum MemoryType {
     MaxMemoryType,
 }
 
+impl TryFrom<u32> for MemoryType {
+    type Error = ();
+
+    fn try_from(val: u32) -> core::result::Result<MemoryType, Self::Error> {
+        if val >= (MemoryType::MaxMemoryType as u32) {
+            return Err(());
+        }
+        Ok(unsafe{ core::mem::transmute(val) })
+    }
+}
+
 pub type PhysAddr = usize;
 
+/// Attributes of a memory region, as found in `MemoryDescriptor::attribute`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MemoryAttribute(u64);
+
+impl MemoryAttribute {
+    pub const UC: MemoryAttribute = MemoryAttribute(0x0000000000000001);
+    pub const WC: MemoryAttribute = MemoryAttribute(0x0000000000000002);
+    pub const WT: MemoryAttribute = MemoryAttribute(0x0000000000000004);
+    pub const WB: MemoryAttribute = MemoryAttribute(0x0000000000000008);
+    pub const UCE: MemoryAttribute = MemoryAttribute(0x0000000000000010);
+    pub const WP: MemoryAttribute = MemoryAttribute(0x0000000000001000);
+    pub const RP: MemoryAttribute = MemoryAttribute(0x0000000000002000);
+    pub const XP: MemoryAttribute = MemoryAttribute(0x0000000000004000);
+    pub const NV: MemoryAttribute = MemoryAttribute(0x0000000000008000);
+    pub const MORE_RELIABLE: MemoryAttribute = MemoryAttribute(0x0000000000010000);
+    pub const RO: MemoryAttribute = MemoryAttribute(0x0000000000020000);
+    pub const SP: MemoryAttribute = MemoryAttribute(0x0000000000040000);
+    pub const CPU_CRYPTO: MemoryAttribute = MemoryAttribute(0x0000000000080000);
+    pub const RUNTIME: MemoryAttribute = MemoryAttribute(0x8000000000000000);
+
+    #[inline]
+    pub const fn empty() -> Self {
+        MemoryAttribute(0)
+    }
+
+    #[inline]
+    pub const fn from_bits(bits: u64) -> Self {
+        MemoryAttribute(bits)
+    }
+
+    #[inline]
+    pub fn bits(self) -> u64 {
+        self.0
+    }
+
+    #[inline]
+    pub fn contains(self, other: MemoryAttribute) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for MemoryAttribute {
+    type Output = MemoryAttribute;
+
+    #[inline]
+    fn bitor(self, other: MemoryAttribute) -> MemoryAttribute {
+        MemoryAttribute(self.0 | other.0)
+    }
+}
+
+/// A single entry of the memory map.
+#[derive(Copy, Clone, Debug)]
+pub struct MemoryDescriptor {
+    // Note: Firmware may return types outside of the `MemoryType` range
+    // (OEM and OS loader defined), so the raw value is kept.
+    pub raw_type: u32,
+    pub physical_start: u64,
+    pub virtual_start: u64,
+    pub number_of_pages: u64,
+    pub attribute: MemoryAttribute,
+}
+
+impl MemoryDescriptor {
+    /// Type of the region, or `None` for OEM and OS loader defined types.
+    #[inline]
+    pub fn memory_type(&self) -> Option<MemoryType> {
+        MemoryType::try_from(self.raw_type).ok()
+    }
+
+    /// Size of the region in bytes.
+    #[inline]
+    pub fn size(&self) -> u64 {
+        self.number_of_pages * (globals::PAGE_SIZE as u64)
+    }
+}
+
+/// Iterator over the descriptors of a memory map.
+///
+/// The stride is the descriptor size reported by the firmware, which may be
+/// larger than `EFI_MEMORY_DESCRIPTOR` to allow for future extensions.
+pub struct MemoryDescriptors<'a> {
+    bytes: &'a [u8],
+    descriptor_size: usize,
+}
+
+impl<'a> Iterator for MemoryDescriptors<'a> {
+    type Item = MemoryDescriptor;
+
+    fn next(&mut self) -> Option<MemoryDescriptor> {
+        if self.bytes.len() < self.descriptor_size {
+            return None;
+        }
+
+        let raw = unsafe { ptr::read_unaligned(self.bytes.as_ptr() as *const efi_types::EFI_MEMORY_DESCRIPTOR) };
+        self.bytes = &self.bytes[self.descriptor_size..];
+
+        Some(MemoryDescriptor {
+            raw_type: raw.Type as _,
+            physical_start: raw.PhysicalStart as _,
+            virtual_start: raw.VirtualStart as _,
+            number_of_pages: raw.NumberOfPages as _,
+            attribute: MemoryAttribute(raw.Attribute as _),
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.bytes.len() / self.descriptor_size;
+        (len, Some(len))
+    }
+}
+
+/// Memory map returned by `GetMemoryMap()`, stored in pages owned by the loader.
+///
+/// The backing pages are allocated as `LoaderData`, so the map stays valid
+/// after `ExitBootServices()` and can be handed over to the kernel.
+/// They are freed on drop while boot services are active.
+pub struct MemoryMap {
+    buffer: *mut u8,
+    buffer_size: usize,
+    map_size: usize,
+    map_key: usize,
+    descriptor_size: usize,
+    descriptor_version: u32,
+}
+
+impl MemoryMap {
+    /// Key identifying this snapshot of the memory map.
+    pub fn map_key(&self) -> usize {
+        self.map_key
+    }
+
+    /// Size of a single descriptor in bytes, as reported by the firmware.
+    pub fn descriptor_size(&self) -> usize {
+        self.descriptor_size
+    }
+
+    pub fn descriptor_version(&self) -> u32 {
+        self.descriptor_version
+    }
+
+    /// Number of descriptors in the map.
+    pub fn len(&self) -> usize {
+        self.map_size / self.descriptor_size
+    }
+
+    /// Raw contents of the map, as written by the firmware.
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.buffer, self.map_size) }
+    }
+
+    pub fn descriptors(&self) -> MemoryDescriptors {
+        MemoryDescriptors{ bytes: self.as_bytes(), descriptor_size: self.descriptor_size }
+    }
+
+    /// Changes the type of the descriptor at `index`.
+    pub(crate) fn set_type(&mut self, index: usize, mtype: MemoryType) {
+        assert!(index < self.len());
+        unsafe {
+            let p = self.buffer.offset((index * self.descriptor_size) as isize) as *mut u32;
+            ptr::write_unaligned(p, mtype as u32);
+        }
+    }
+}
+
+impl Drop for MemoryMap {
+    fn drop(&mut self) {
+        // After `ExitBootServices()` the pages belong to the application anyway.
+        unsafe {
+            if let Some(table) = globals::BOOT_SERVICES_TABLE {
+                let _ = table.free_pages(self.buffer as _, self.buffer_size / globals::PAGE_SIZE);
+            }
+        }
+    }
+}
+
+/// Memory map written into a buffer provided by the caller.
+pub struct MemoryMapBuf<'a> {
+    bytes: &'a [u8],
+    map_key: usize,
+    descriptor_size: usize,
+    descriptor_version: u32,
+}
+
+impl<'a> MemoryMapBuf<'a> {
+    /// Key identifying this snapshot of the memory map.
+    pub fn map_key(&self) -> usize {
+        self.map_key
+    }
+
+    /// Size of a single descriptor in bytes, as reported by the firmware.
+    pub fn descriptor_size(&self) -> usize {
+        self.descriptor_size
+    }
+
+    pub fn descriptor_version(&self) -> u32 {
+        self.descriptor_version
+    }
+
+    /// Number of descriptors in the map.
+    pub fn len(&self) -> usize {
+        self.bytes.len() / self.descriptor_size
+    }
+
+    /// Raw contents of the map, as written by the firmware.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    pub fn descriptors(&self) -> MemoryDescriptors<'a> {
+        MemoryDescriptors{ bytes: self.bytes, descriptor_size: self.descriptor_size }
+    }
+}
+
 #[repr(C)]
 pub struct BootServices {
     table: efi_types::EFI_BOOT_SERVICES,
 }
 
 impl BootServices {
-    pub fn allocate_pages(&mut self, atype: AllocateType, mtype: MemoryType, pages: usize, addr: PhysAddr) -> Result<PhysAddr> {
+    pub fn allocate_pages(&self, atype: AllocateType, mtype: MemoryType, pages: usize, addr: PhysAddr) -> Result<PhysAddr> {
         let mut addr: efi_types::EFI_PHYSICAL_ADDRESS = addr as _;
         let allocfn = self.table.AllocatePages.unwrap();
         let status = unsafe { allocfn(atype as _, mtype as _, pages as _, &mut addr) };
         status_to_result(status, addr as _)
     }
+
+    /// Frees memory pages.
+    ///
+    /// **Errors**
+    ///
+    /// * `EFI_NOT_FOUND`
+    ///     * The requested memory pages were not allocated with `allocate_pages()`.
+    ///
+    /// * `EFI_INVALID_PARAMETER`
+    ///     * `addr` is not page aligned or `pages` is invalid.
+    ///
+    pub unsafe fn free_pages(&self, addr: PhysAddr, pages: usize) -> Result<()> {
+        let func = self.table.FreePages.unwrap();
+        let status = func(addr as _, pages as _);
+        status_to_result(status, ())
+    }
+
+    /// Allocates pool memory.
+    ///
+    /// The returned buffer is 8-byte aligned.
+    ///
+    /// **Errors**
+    ///
+    /// * `EFI_OUT_OF_RESOURCES`
+    ///     * The pool requested could not be allocated.
+    ///
+    /// * `EFI_INVALID_PARAMETER`
+    ///     * `mtype` is not a valid type for allocation.
+    ///
+    pub fn allocate_pool(&self, mtype: MemoryType, size: usize) -> Result<*mut u8> {
+        let mut buffer = ptr::null_mut();
+        let func = self.table.AllocatePool.unwrap();
+        let status = unsafe { func(mtype as _, size as _, &mut buffer) };
+        status_to_result(status, buffer as *mut u8)
+    }
+
+    /// Returns pool memory to the system.
+    ///
+    /// **Errors**
+    ///
+    /// * `EFI_INVALID_PARAMETER`
+    ///     * `buffer` was invalid.
+    ///
+    pub unsafe fn free_pool(&self, buffer: *mut u8) -> Result<()> {
+        let func = self.table.FreePool.unwrap();
+        let status = func(buffer as _);
+        status_to_result(status, ())
+    }
+
+    /// Calls `GetMemoryMap()` with the given buffer.
+    ///
+    /// On `EFI_BUFFER_TOO_SMALL`, `size` is updated with the required buffer size.
+    unsafe fn get_memory_map_raw(&self, buffer: *mut u8, size: &mut usize) -> Result<(usize, usize, u32)> {
+        let mut map_size: efi_types::UINTN = *size as _;
+        let mut map_key: efi_types::UINTN = 0;
+        let mut descriptor_size: efi_types::UINTN = 0;
+        let mut descriptor_version: u32 = 0;
+
+        let func = self.table.GetMemoryMap.unwrap();
+        let status = func(&mut map_size, buffer as *mut efi_types::EFI_MEMORY_DESCRIPTOR,
+                          &mut map_key, &mut descriptor_size, &mut descriptor_version);
+        *size = map_size as _;
+        let result: (usize, usize, u32) = status_to_result(status, (map_key as _, descriptor_size as _, descriptor_version))?;
+        // Descriptors may grow in future versions, but never shrink.
+        if result.1 < mem::size_of::<efi_types::EFI_MEMORY_DESCRIPTOR>() {
+            return Err(Error::INCOMPATIBLE_VERSION);
+        }
+        Ok(result)
+    }
+
+    /// Returns the size of buffer currently needed to hold the memory map.
+    ///
+    /// Note that allocating the buffer may itself grow the map by a descriptor or two.
+    pub fn memory_map_size(&self) -> Result<usize> {
+        let mut size = 0;
+        match unsafe { self.get_memory_map_raw(ptr::null_mut(), &mut size) } {
+            Err(ref e) if *e == Error::BUFFER_TOO_SMALL => Ok(size),
+            Err(e) => Err(e),
+            Ok(_) => Ok(size),
+        }
+    }
+
+    /// Returns the current memory map.
+    ///
+    /// The map is stored in newly allocated `LoaderData` pages, which remain
+    /// valid after `ExitBootServices()`.
+    ///
+    /// **Errors**
+    ///
+    /// * `EFI_OUT_OF_RESOURCES`
+    ///     * The pages for the map could not be allocated.
+    ///
+    /// * `EFI_INCOMPATIBLE_VERSION`
+    ///     * The firmware's descriptors are smaller than `EFI_MEMORY_DESCRIPTOR`.
+    ///
+    pub fn get_memory_map(&self) -> Result<MemoryMap> {
+        self.get_memory_map_with_slack(0)
+    }
+
+    /// Writes the current memory map into `buffer`.
+    ///
+    /// The buffer should be 8-byte aligned. Use `memory_map_size()` to find out how big it needs to be.
+    ///
+    /// **Errors**
+    ///
+    /// * `EFI_BUFFER_TOO_SMALL`
+    ///     * The buffer is too small to hold the current memory map.
+    ///
+    pub fn get_memory_map_into<'a>(&self, buffer: &'a mut [u8]) -> Result<MemoryMapBuf<'a>> {
+        let mut size = buffer.len();
+        let (map_key, descriptor_size, descriptor_version) =
+            unsafe { self.get_memory_map_raw(buffer.as_mut_ptr(), &mut size) }?;
+
+        Ok(MemoryMapBuf {
+            bytes: &buffer[..size],
+            map_key: map_key,
+            descriptor_size: descriptor_size,
+            descriptor_version: descriptor_version,
+        })
+    }
+
+    /// Fetches the current memory map into freshly allocated pages.
+    ///
+    /// `extra_descriptors` slots are reserved beyond the current size of the map,
+    /// so that the map can be fetched again into the same buffer after it changes
+    /// without allocating.
+    pub(crate) fn get_memory_map_with_slack(&self, extra_descriptors: usize) -> Result<MemoryMap> {
+        let mut size = 0;
+        loop {
+            match unsafe { self.get_memory_map_raw(ptr::null_mut(), &mut size) } {
+                Err(ref e) if *e == Error::BUFFER_TOO_SMALL => {},
+                Err(e) => return Err(e),
+                Ok(_) => return Err(Error::INVALID_PARAMETER),
+            }
+
+            // Assume descriptors aren't bigger than 64 bytes, which is true for every
+            // known firmware, and the allocation itself may add up to two descriptors.
+            let buffer_size = size + (extra_descriptors + 2) * 64;
+            let pages = ((buffer_size - 1) / globals::PAGE_SIZE) + 1;
+            let buffer = self.allocate_pages(AllocateType::AllocateAnyPages, MemoryType::LoaderData, pages, 0)? as *mut u8;
+
+            size = pages * globals::PAGE_SIZE;
+            match unsafe { self.get_memory_map_raw(buffer, &mut size) } {
+                Ok((map_key, descriptor_size, descriptor_version)) => {
+                    return Ok(MemoryMap {
+                        buffer: buffer,
+                        buffer_size: pages * globals::PAGE_SIZE,
+                        map_size: size,
+                        map_key: map_key,
+                        descriptor_size: descriptor_size,
+                        descriptor_version: descriptor_version,
+                    });
+                },
+                Err(e) => {
+                    let _ = unsafe { self.free_pages(buffer as _, pages) };
+                    if e == Error::BUFFER_TOO_SMALL {
+                        continue;
+                    }
+                    return Err(e);
+                },
+            }
+        }
+    }
+
+    /// Fetches the memory map again into the buffer of an existing `MemoryMap`.
+    ///
+    /// This doesn't allocate, so it's safe to call after a failed `ExitBootServices()`.
+    pub(crate) fn refresh_memory_map(&self, map: &mut MemoryMap) -> Result<()> {
+        let mut size = map.buffer_size;
+        let (map_key, descriptor_size, descriptor_version) = unsafe { self.get_memory_map_raw(map.buffer, &mut size) }?;
+        map.map_size = size;
+        map.map_key = map_key;
+        map.descriptor_size = descriptor_size;
+        map.descriptor_version = descriptor_version;
+        Ok(())
+    }
+
+    unsafe fn create_event_raw(&self, event_type: EventType, tpl: Tpl, notify: Option<Notify>, group: Option<&Guid>) -> Result<Event> {
+        let context = notify.map(|f| Box::into_raw(Box::new(f)));
+        let (notify_fn, context_ptr) = match context {
+            Some(p) => (Some(event::notify_trampoline as _), p as *mut _),
+            None => (None, ptr::null_mut()),
+        };
+
+        let mut raw = ptr::null_mut();
+        let status = match group {
+            None => {
+                let func = self.table.CreateEvent.unwrap();
+                func(event_type.bits(), tpl.0 as _, notify_fn, context_ptr, &mut raw)
+            },
+            Some(group) => {
+                let func = self.table.CreateEventEx.unwrap();
+                func(event_type.bits(), tpl.0 as _, notify_fn, context_ptr, group as *const Guid as *const _, &mut raw)
+            },
+        };
+
+        match status_to_result(status, ()) {
+            Ok(()) => Ok(Event::from_raw(raw, context)),
+            Err(e) => {
+                if let Some(p) = context {
+                    drop(Box::from_raw(p));
+                }
+                Err(e)
+            },
+        }
+    }
+
+    /// Creates an event without a notification function.
+    ///
+    /// **Errors**
+    ///
+    /// * `EFI_INVALID_PARAMETER`
+    ///     * `event_type` is not a supported type, or requires a notification function.
+    ///
+    /// * `EFI_OUT_OF_RESOURCES`
+    ///     * The event could not be allocated.
+    ///
+    pub fn create_event(&self, event_type: EventType) -> Result<Event> {
+        unsafe { self.create_event_raw(event_type, Tpl::APPLICATION, None, None) }
+    }
+
+    /// Creates an event with `notify` as the notification function.
+    ///
+    /// `notify` is called at `tpl`, which must be higher than `Tpl::APPLICATION`.
+    /// For `NOTIFY_WAIT` events, it's called while the event is being waited on or checked,
+    /// for `NOTIFY_SIGNAL` events, when the event is signaled.
+    ///
+    /// **Errors**
+    ///
+    /// * `EFI_INVALID_PARAMETER`
+    ///     * `event_type` or `tpl` is not supported.
+    ///
+    /// * `EFI_OUT_OF_RESOURCES`
+    ///     * The event could not be allocated.
+    ///
+    pub fn create_notify_event<F: FnMut() + 'static>(&self, event_type: EventType, tpl: Tpl, notify: F) -> Result<Event> {
+        unsafe { self.create_event_raw(event_type, tpl, Some(Box::new(notify)), None) }
+    }
+
+    /// Creates an event in the event group `group`.
+    ///
+    /// Signaling any event in the group signals all of them.
+    /// See `create_notify_event()` for the meaning of the other arguments.
+    ///
+    pub fn create_event_ex<F: FnMut() + 'static>(&self, event_type: EventType, tpl: Tpl, notify: F, group: &Guid) -> Result<Event> {
+        unsafe { self.create_event_raw(event_type, tpl, Some(Box::new(notify)), Some(group)) }
+    }
+
+    pub(crate) unsafe fn close_event_raw(&self, event: efi_types::EFI_EVENT) -> Result<()> {
+        let func = self.table.CloseEvent.unwrap();
+        status_to_result(func(event), ())
+    }
+
+    /// Closes an event.
+    ///
+    /// This is what dropping the `Event` does, except that failure is reported.
+    pub fn close_event(&self, event: Event) -> Result<()> {
+        let result = unsafe { self.close_event_raw(event.as_raw()) };
+        if result.is_ok() {
+            // Already closed, only free the notification function.
+            event.forget_closed();
+        }
+        result
+    }
+
+    /// Signals an event.
+    pub fn signal_event(&self, event: &Event) -> Result<()> {
+        let func = self.table.SignalEvent.unwrap();
+        let status = unsafe { func(event.as_raw()) };
+        status_to_result(status, ())
+    }
+
+    /// Stops execution until one of the events is signaled, and returns its index.
+    ///
+    /// **Errors**
+    ///
+    /// * `EFI_INVALID_PARAMETER`
+    ///     * `events` is empty, or one of the events is of type `NOTIFY_SIGNAL`.
+    ///     * There are more than `MAX_WAIT_EVENTS` events. Use `wait_for_event_raw()` instead.
+    ///
+    /// * `EFI_UNSUPPORTED`
+    ///     * The current TPL is not `Tpl::APPLICATION`.
+    ///
+    pub fn wait_for_event(&self, events: &[&Event]) -> Result<usize> {
+        if events.len() > MAX_WAIT_EVENTS {
+            return Err(Error::INVALID_PARAMETER);
+        }
+        let mut raw = [ptr::null_mut(); MAX_WAIT_EVENTS];
+        for (raw, event) in raw.iter_mut().zip(events) {
+            *raw = event.as_raw();
+        }
+        unsafe { self.wait_for_event_raw(&raw[..events.len()]) }
+    }
+
+    /// Same as `wait_for_event()`, for any number of events given as raw handles.
+    ///
+    /// Unsafe because the handles must be events that are still open.
+    pub unsafe fn wait_for_event_raw(&self, events: &[efi_types::EFI_EVENT]) -> Result<usize> {
+        let mut index = 0;
+        let func = self.table.WaitForEvent.unwrap();
+        // The firmware doesn't write to the array, despite the signature.
+        let status = func(events.len() as _, events.as_ptr() as *mut _, &mut index);
+        status_to_result(status, index as usize)
+    }
+
+    /// Checks whether an event is in the signaled state, and clears that state.
+    ///
+    /// **Errors**
+    ///
+    /// * `EFI_INVALID_PARAMETER`
+    ///     * The event is of type `NOTIFY_SIGNAL`.
+    ///
+    pub fn check_event(&self, event: &Event) -> Result<bool> {
+        let func = self.table.CheckEvent.unwrap();
+        let status = unsafe { func(event.as_raw()) };
+        match status_to_result(status, true) {
+            Err(Error::NOT_READY) => Ok(false),
+            result => result,
+        }
+    }
+
+    /// Sets the type of timer and the trigger time for a timer event.
+    ///
+    /// **Errors**
+    ///
+    /// * `EFI_INVALID_PARAMETER`
+    ///     * The event is not a timer event.
+    ///
+    pub fn set_timer(&self, event: &Event, timer: Timer) -> Result<()> {
+        let (delay, trigger_time) = timer.to_raw();
+        let func = self.table.SetTimer.unwrap();
+        let status = unsafe { func(event.as_raw(), delay as _, trigger_time as _) };
+        status_to_result(status, ())
+    }
+
+    pub(crate) unsafe fn raise_tpl_raw(&self, tpl: Tpl) -> Tpl {
+        let func = self.table.RaiseTPL.unwrap();
+        Tpl(func(tpl.0 as _) as _)
+    }
+
+    /// The current task priority level.
+    pub(crate) fn current_tpl(&self) -> Tpl {
+        // There's no call for it, but raising the level to the highest one returns it.
+        unsafe {
+            let current = self.raise_tpl_raw(Tpl::HIGH_LEVEL);
+            self.restore_tpl(current);
+            current
+        }
+    }
+
+    /// Raises the task priority level, returning a guard that restores it when dropped.
+    ///
+    /// If the current level is already higher than `tpl`, it's kept,
+    /// as `RaiseTPL()` must never lower it.
+    ///
+    pub fn raise_tpl(&self, tpl: Tpl) -> TplGuard {
+        let tpl = cmp::max(tpl, self.current_tpl());
+        TplGuard::new(unsafe { self.raise_tpl_raw(tpl) })
+    }
+
+    /// Restores the task priority level to its previous value.
+    pub(crate) unsafe fn restore_tpl(&self, old: Tpl) {
+        let func = self.table.RestoreTPL.unwrap();
+        func(old.0 as _);
+    }
+
+    /// Arranges for `event` to be signaled whenever the protocol is installed on a handle.
+    ///
+    /// Returns a registration to use with `SearchType::ByRegisterNotify`,
+    /// to find out which handles the protocol was installed on.
+    ///
+    pub fn register_protocol_notify(&self, protocol: &Guid, event: &Event) -> Result<Registration> {
+        let mut registration = ptr::null_mut();
+        let func = self.table.RegisterProtocolNotify.unwrap();
+        let status = unsafe { func(protocol as *const Guid as *mut _, event.as_raw(), &mut registration) };
+        status_to_result(status, Registration(registration))
+    }
+
+    /// Returns the handles matching the search.
+    ///
+    /// **Errors**
+    ///
+    /// * `EFI_NOT_FOUND`
+    ///     * No handles match the search.
+    ///
+    /// * `EFI_OUT_OF_RESOURCES`
+    ///     * There is not enough pool memory to store the matching results.
+    ///
+    pub fn locate_handle_buffer(&self, search: SearchType) -> Result<PoolSlice<Handle>> {
+        let (search_type, protocol, key) = match search {
+            SearchType::AllHandles => (0, ptr::null(), ptr::null_mut()),
+            SearchType::ByRegisterNotify(registration) => (1, ptr::null(), registration.0),
+            SearchType::ByProtocol(guid) => (2, guid as *const Guid, ptr::null_mut()),
+        };
+
+        let mut count = 0;
+        let mut buffer = ptr::null_mut();
+        let func = self.table.LocateHandleBuffer.unwrap();
+        let status = unsafe { func(search_type as _, protocol as *mut _, key, &mut count, &mut buffer) };
+        status_to_result(status, ())?;
+        Ok(unsafe { PoolSlice::from_raw(buffer as *mut Handle, count as usize) })
+    }
+
+    /// Returns the GUIDs of the protocols installed on `handle`.
+    pub fn protocols_per_handle(&self, handle: Handle) -> Result<ProtocolGuids> {
+        let mut count = 0;
+        let mut buffer = ptr::null_mut();
+        let func = self.table.ProtocolsPerHandle.unwrap();
+        let status = unsafe { func(handle.as_raw(), &mut buffer, &mut count) };
+        status_to_result(status, ())?;
+        Ok(ProtocolGuids{ guids: unsafe { PoolSlice::from_raw(buffer as *mut *const Guid, count as usize) } })
+    }
+
+    /// Queries `handle` for the protocol interface `P`, like `HandleProtocol()`.
+    ///
+    /// Implemented with `OpenProtocol()` and `ByHandleProtocol`, as the spec recommends,
+    /// so the protocol is closed when the returned reference is dropped.
+    ///
+    /// **Errors**
+    ///
+    /// * `EFI_UNSUPPORTED`
+    ///     * `handle` does not support the protocol.
+    ///
+    pub fn handle_protocol<P: Protocol>(&self, handle: Handle) -> Result<ProtocolRef<P>> {
+        self.open_protocol(handle, OpenProtocolAttributes::ByHandleProtocol)
+    }
+
+    /// Opens the protocol interface `P` on `handle` on behalf of this image.
+    ///
+    /// The protocol is closed when the returned reference is dropped.
+    ///
+    /// **Errors**
+    ///
+    /// * `EFI_UNSUPPORTED`
+    ///     * `handle` does not support the protocol.
+    ///
+    /// * `EFI_ACCESS_DENIED`
+    ///     * The protocol is already opened with `Exclusive`.
+    ///
+    pub fn open_protocol<P: Protocol>(&self, handle: Handle, attributes: OpenProtocolAttributes) -> Result<ProtocolRef<P>> {
+        unsafe {
+            let agent = Handle::from_raw(globals::IMAGE_HANDLE);
+            self.open_protocol_raw(handle, &P::GUID, agent, None, attributes)
+        }
+    }
+
+    /// Opens the protocol interface `P` on `handle`.
+    ///
+    /// `agent` is the image opening the protocol, and `controller` the controller
+    /// that requires it, if opened by a driver.
+    ///
+    pub fn open_protocol_ex<P: Protocol>(&self, handle: Handle, agent: Handle, controller: Option<Handle>,
+                                         attributes: OpenProtocolAttributes) -> Result<ProtocolRef<P>> {
+        unsafe { self.open_protocol_raw(handle, &P::GUID, agent, controller, attributes) }
+    }
+
+    /// Opens the protocol interface identified by `guid` on `handle`.
+    ///
+    /// Unsafe because `P` must be the interface structure of the protocol.
+    ///
+    pub unsafe fn open_protocol_raw<'a, P>(&'a self, handle: Handle, guid: &Guid, agent: Handle, controller: Option<Handle>,
+                                           attributes: OpenProtocolAttributes) -> Result<ProtocolRef<'a, P>> {
+        let mut interface = ptr::null_mut();
+        let controller_raw = controller.map_or(ptr::null_mut(), |c| c.as_raw());
+        let func = self.table.OpenProtocol.unwrap();
+        let status = func(handle.as_raw(), guid as *const Guid as *mut _, &mut interface,
+                          agent.as_raw(), controller_raw, attributes.bits());
+        status_to_result(status, ())?;
+        Ok(ProtocolRef::new(self, interface as *mut P, handle, *guid, agent, controller))
+    }
+
+    /// Opens the protocol interface `P` on the first handle that supports it.
+    ///
+    /// **Errors**
+    ///
+    /// * `EFI_NOT_FOUND`
+    ///     * No handle supports the protocol.
+    ///
+    pub fn locate_protocol<P: Protocol>(&self) -> Result<ProtocolRef<P>> {
+        let handles = self.locate_handle_buffer(SearchType::ByProtocol(&P::GUID))?;
+        match handles.first() {
+            Some(&handle) => self.handle_protocol(handle),
+            None => Err(Error::NOT_FOUND),
+        }
+    }
+
+    /// Closes a protocol opened with `open_protocol_raw()`.
+    ///
+    /// Dropping the `ProtocolRef` does this, so it's only needed for interfaces
+    /// obtained some other way.
+    ///
+    pub unsafe fn close_protocol(&self, handle: Handle, guid: &Guid, agent: Handle, controller: Option<Handle>) -> Result<()> {
+        let controller_raw = controller.map_or(ptr::null_mut(), |c| c.as_raw());
+        let func = self.table.CloseProtocol.unwrap();
+        let status = func(handle.as_raw(), guid as *const Guid as *mut _, agent.as_raw(), controller_raw);
+        status_to_result(status, ())
+    }
+
+    /// Loads an image into memory, without starting it.
+    ///
+    /// `parent` is the image on whose behalf the image is loaded,
+    /// usually `BootContext::image_handle()`.
+    ///
+    /// **Errors**
+    ///
+    /// * `EFI_NOT_FOUND`
+    ///     * The device path doesn't lead to a file.
+    ///
+    /// * `EFI_UNSUPPORTED`
+    ///     * The image type is not supported.
+    ///
+    /// * `EFI_LOAD_ERROR`
+    ///     * The image format is corrupt.
+    ///
+    /// * `EFI_SECURITY_VIOLATION`
+    ///     * The image was rejected by the platform security policy, e.g. Secure Boot.
+    ///       The image is still loaded in that case, but the firmware won't start it.
+    ///
+    pub fn load_image(&self, parent: Handle, source: ImageSource) -> Result<Image> {
+        let (boot_policy, device_path, buffer, size) = match source {
+            ImageSource::DevicePath{ device_path, boot_policy } =>
+                (boot_policy, device_path.as_ptr(), ptr::null(), 0),
+            ImageSource::Buffer{ data, device_path } =>
+                (false, device_path.map_or(ptr::null(), |p| p.as_ptr()), data.as_ptr(), data.len()),
+        };
+
+        let mut handle = ptr::null_mut();
+        let func = self.table.LoadImage.unwrap();
+        let status = unsafe { func(boot_policy as _, parent.as_raw(), device_path as *mut _,
+                                   buffer as *mut _, size as _, &mut handle) };
+        match status_to_result(status, ()) {
+            Ok(()) => Ok(Image::new(unsafe { Handle::from_raw(handle) })),
+            Err(e) => {
+                if e == Error::SECURITY_VIOLATION && !handle.is_null() {
+                    // The spec leaves the image loaded, but it's of no use to us.
+                    let _ = unsafe { self.unload_image_raw(Handle::from_raw(handle)) };
+                }
+                Err(e)
+            },
+        }
+    }
+
+    /// Transfers control to a loaded image, and returns once it exits.
+    ///
+    /// An image can only be started once. The returned error is about starting
+    /// the image, the image's own exit status is in `ImageExit::status`.
+    ///
+    /// **Errors**
+    ///
+    /// * `EFI_INVALID_PARAMETER`
+    ///     * The image was already started.
+    ///
+    /// * `EFI_SECURITY_VIOLATION`
+    ///     * The platform security policy forbids starting the image.
+    ///
+    /// Any other error of the firmware that keeps the image from running is returned
+    /// as well, and the image is then still loaded. An image that ran and returned
+    /// an error, including the above, gives an `ImageExit`.
+    ///
+    pub fn start_image(&self, image: &mut Image) -> Result<ImageExit> {
+        if image.is_started() {
+            return Err(Error::INVALID_PARAMETER);
+        }
+
+        let mut exit_data_size = 0;
+        let mut exit_data = ptr::null_mut();
+        let func = self.table.StartImage.unwrap();
+        let status = unsafe { func(image.handle().as_raw(), &mut exit_data_size, &mut exit_data) };
+
+        let exit_data = if exit_data.is_null() {
+            None
+        } else {
+            Some(unsafe { ExitData::from_raw(exit_data as *mut u16, exit_data_size as usize) })
+        };
+
+        if let Err(e) = status_to_status(status) {
+            // The image may also have run and returned this status, in which case the
+            // firmware unloaded it. Only an image that never ran still has its handle.
+            if exit_data.is_none() {
+                let loaded = self.open_protocol::<LoadedImage>(image.handle(), OpenProtocolAttributes::GetProtocol);
+                if loaded.is_ok() {
+                    return Err(e);
+                }
+            }
+        }
+
+        // An application is unloaded by the firmware when it exits,
+        // and a driver stays resident until explicitly unloaded.
+        image.set_started();
+
+        Ok(ImageExit{ status: status_to_status(status), exit_data: exit_data })
+    }
+
+    /// Unloads an image that was loaded but not started, or a driver that supports unloading.
+    ///
+    /// **Errors**
+    ///
+    /// * `EFI_UNSUPPORTED`
+    ///     * The image has been started, and does not support unload.
+    ///
+    pub fn unload_image(&self, image: Image) -> Result<()> {
+        let handle = image.handle();
+        mem::forget(image);
+        unsafe { self.unload_image_raw(handle) }
+    }
+
+    pub(crate) unsafe fn unload_image_raw(&self, handle: Handle) -> Result<()> {
+        let func = self.table.UnloadImage.unwrap();
+        let status = func(handle.as_raw());
+        status_to_result(status, ())
+    }
+
+    /// Terminates `image`, returning `status` to whoever started it.
+    ///
+    /// `exit_data` is passed to the parent, and should start with a NUL-terminated
+    /// description of the exit reason. It must be allocated from pool,
+    /// because ownership is transferred to the parent.
+    ///
+    /// Only returns if the call failed. Unsafe because nothing on the stack is
+    /// dropped, and because `image` must be the running image, or one that
+    /// was loaded but not started.
+    ///
+    /// **Errors**
+    ///
+    /// * `EFI_INVALID_PARAMETER`
+    ///     * The image has been loaded and started, but is not the currently executing image.
+    ///
+    pub unsafe fn exit(&self, image: Handle, status: Result<Status>, exit_data: Option<PoolSlice<u16>>) -> Error {
+        let status = match status {
+            Ok(s) => s.into_raw(),
+            Err(e) => e.into_raw(),
+        };
+        let (size, data) = match exit_data {
+            Some(data) => {
+                let (ptr, len) = PoolSlice::into_raw(data);
+                (len * 2, ptr)
+            },
+            None => (0, ptr::null_mut()),
+        };
+
+        let func = self.table.Exit.unwrap();
+        let status = func(image.as_raw(), status, size as _, data as *mut _);
+        match status_to_result(status, ()) {
+            Err(e) => e,
+            // Shouldn't happen, but never claim success.
+            Ok(()) => Error::INVALID_PARAMETER,
+        }
+    }
+
+    /// Terminates all boot services.
+    ///
+    /// **Errors**
+    ///
+    /// * `EFI_INVALID_PARAMETER`
+    ///     * `map_key` is incorrect.
+    ///
+    pub(crate) unsafe fn exit_boot_services(&self, image_handle: efi_types::EFI_HANDLE, map_key: usize) -> Result<()> {
+        let func = self.table.ExitBootServices.unwrap();
+        let status = func(image_handle, map_key as _);
+        status_to_result(status, ())
+    }
 }
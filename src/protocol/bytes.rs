@@ -0,0 +1,30 @@
+// Little-endian fields of firmware structures handled as bytes,
+// such as device path nodes and file information.
+
+use alloc::vec::Vec;
+
+pub(crate) fn read_u16(b: &[u8], o: usize) -> u16 {
+    (b[o] as u16) | (b[o+1] as u16) << 8
+}
+
+pub(crate) fn read_u32(b: &[u8], o: usize) -> u32 {
+    (read_u16(b, o) as u32) | (read_u16(b, o+2) as u32) << 16
+}
+
+pub(crate) fn read_u64(b: &[u8], o: usize) -> u64 {
+    (read_u32(b, o) as u64) | (read_u32(b, o+4) as u64) << 32
+}
+
+pub(crate) fn write_u16(out: &mut Vec<u8>, v: u16) {
+    out.extend_from_slice(&[v as u8, (v >> 8) as u8]);
+}
+
+pub(crate) fn write_u32(out: &mut Vec<u8>, v: u32) {
+    write_u16(out, v as u16);
+    write_u16(out, (v >> 16) as u16);
+}
+
+pub(crate) fn write_u64(out: &mut Vec<u8>, v: u64) {
+    write_u32(out, v as u32);
+    write_u32(out, (v >> 32) as u32);
+}
@@ -2,6 +2,8 @@ use efi_types;
 use core;
 
 use protocol::{ Color, Guid, Status, Result, Error, status_to_result, status_to_status };
+use string::CStr16;
+use ucs2;
 
 use core::convert::TryFrom;
 use core::fmt;
@@ -52,9 +54,11 @@ pub struct Mode {
     pub cursor_visible: bool,
 }
 
-impl Protocol {
-    pub const GUID: Guid = Guid(0x387477c2,0x69c7,0x11d2,[0x8e,0x39,0x00,0xa0,0xc9,0x69,0x72,0x3b]);
+unsafe impl ::protocol::Protocol for Protocol {
+    const GUID: Guid = Guid(0x387477c2,0x69c7,0x11d2,[0x8e,0x39,0x00,0xa0,0xc9,0x69,0x72,0x3b]);
+}
 
+impl Protocol {
     pub fn mode(&mut self) -> Mode {
         let m = unsafe {&*self.interface.Mode};
         Mode {
@@ -174,10 +178,17 @@ impl Protocol {
     ///     * Some of the characters in the string cannot be rendered by one or
     ///     more of the output devices mapped by the EFI handle.
     ///
+    /// * `EFI_INVALID_PARAMETER`
+    ///     * The string is not NUL-terminated.
+    ///
     pub fn test_string_utf16(&mut self, string: &[u16]) -> Result<()> {
-        assert!(string[string.len()-1] == 0);
+        if string.last() != Some(&0) {
+            return Err(Error::INVALID_PARAMETER);
+        }
 
-        unimplemented!()
+        let func = self.interface.TestString.unwrap();
+        let status = unsafe { func(&mut self.interface, string.as_ptr() as *mut u16) };
+        status_to_result(status, ())
     }
 
     /// Returns information for an available text mode that the output device(s) supports.
@@ -205,7 +216,20 @@ impl Protocol {
     ///     * The mode number was not valid.
     ///
     pub fn query_mode(&mut self, mode_number: ModeNumber) -> Result<(Column, Row)> {
-        unimplemented!()
+        let mut columns = 0;
+        let mut rows = 0;
+        let func = self.interface.QueryMode.unwrap();
+        let status = unsafe { func(&mut self.interface, mode_number as _, &mut columns, &mut rows) };
+        status_to_result(status, (columns as _, rows as _))
+    }
+
+    /// Returns an iterator over all text modes supported by the output device(s).
+    ///
+    /// Yields `(mode_number, number_of_columns, number_of_rows)` for every mode
+    /// in `0..max_mode` that `query_mode()` reports as valid.
+    pub fn modes(&mut self) -> Modes {
+        let max_mode = self.mode().max_mode;
+        Modes{ protocol: self, next: 0, max_mode: max_mode }
     }
 
     /// Sets the output device(s) to a specified mode.
@@ -226,7 +250,9 @@ impl Protocol {
     ///     * The mode number was not valid.
     ///
     pub fn set_mode(&mut self, mode_number: ModeNumber) -> Result<()> {
-        unimplemented!()
+        let func = self.interface.SetMode.unwrap();
+        let status = unsafe { func(&mut self.interface, mode_number as _) };
+        status_to_result(status, ())
     }
 
     /// Sets the background and foreground colors for the `output_string()'
@@ -246,7 +272,9 @@ impl Protocol {
     ///     * The device had an error and could not complete the request.
     ///
     pub fn set_attribute(&mut self, attr: Attribute) -> Result<()> {
-        unimplemented!()
+        let func = self.interface.SetAttribute.unwrap();
+        let status = unsafe { func(&mut self.interface, attr.code as _) };
+        status_to_result(status, ())
     }
 
     ///
@@ -288,7 +316,9 @@ impl Protocol {
     ///     or the cursor position is invalid for the current mode.
     ///
     pub fn set_cursor_position(&mut self, column: Column, row: Row) -> Result<()> {
-        unimplemented!()
+        let func = self.interface.SetCursorPosition.unwrap();
+        let status = unsafe { func(&mut self.interface, column as _, row as _) };
+        status_to_result(status, ())
     }
 
     /// Makes the cursor visible or invisible.
@@ -303,7 +333,23 @@ impl Protocol {
     ///     * The output device does not support visibility control of the cursor.
     ///
     pub fn enable_cursor(&mut self, visible_cursor: bool) -> Result<()> {
-        unimplemented!()
+        let func = self.interface.EnableCursor.unwrap();
+        let status = unsafe { func(&mut self.interface, visible_cursor as u8) };
+        status_to_result(status, ())
+    }
+
+    /// Writes a string to the output device.
+    ///
+    /// Like `output_string_utf16()`, but the terminator is guaranteed by the type.
+    pub fn output_cstr16(&mut self, string: &CStr16) -> Result<Status> {
+        self.output_string_utf16(string.as_slice_with_nul())
+    }
+
+    /// Verifies that all characters in a string can be output to the target device.
+    ///
+    /// Like `test_string_utf16()`, but the terminator is guaranteed by the type.
+    pub fn test_cstr16(&mut self, string: &CStr16) -> Result<()> {
+        self.test_string_utf16(string.as_slice_with_nul())
     }
 
     pub fn output_bytes(&mut self, b: &[u8]) -> Result<Status> {
@@ -316,58 +362,31 @@ impl Protocol {
     /// Writes a string to the output device.
     ///
     /// This is a convenience method that wraps `output_string_utf16`.
+    /// Newlines are expanded to `"\r\n"`, and characters outside of the
+    /// Basic Multilingual Plane are replaced with U+FFFD, since the console
+    /// is only required to support UCS-2.
     ///
     pub fn output_string(&mut self, s: &str) -> Result<Status> {
+        self.output_string_with_policy(s, ucs2::Policy::Replace)
+    }
 
+    /// Like `output_string()`, but with explicit handling of characters
+    /// outside of the Basic Multilingual Plane.
+    pub fn output_string_with_policy(&mut self, s: &str, policy: ucs2::Policy) -> Result<Status> {
         let buffer = &mut [0_u16; 32];
-        let mut i = 0;
+        let mut encoder = ucs2::Encoder::new(s, policy).expand_newlines(true);
         let mut stat = Status::success();
 
-        for c in s.chars() {
-            if i >= buffer.len() - 2 {
-                buffer[i] = 0;
-                let status = try!(self.output_string_utf16(&buffer[..i+1]));
-                if !status.is_success() {
-                    stat = status;
-                }
-                i = 0;
+        loop {
+            let len = encoder.encode_chunk(buffer);
+            if len == 0 {
+                break;
             }
 
-            let code = c as u32;
-
-            // Expand newline.
-            if code == '\n' as u32 {
-                buffer[i] = '\r' as u16;
-                buffer[i+1] = '\n' as u16;
-                i += 2;
-                continue;
+            let status = self.output_string_utf16(&buffer[..len+1])?;
+            if !status.is_success() {
+                stat = status;
             }
-
-            if code >= 0xd800 && code < 0xe000 {
-                // Illegal code points.
-                buffer[i] = 0xfffd;
-                i += 1;
-                continue;
-            }
-
-            if code < 0x10000 {
-                buffer[i] = code as u16;
-                i += 1;
-            } else {
-                // Emit surrogates.
-                let code = code - 0x10000;
-                let high_surrogate = 0xd800 + code >> 10;
-                let low_surrogate = 0xdc00 + code & 0x03ff;
-                buffer[i] = high_surrogate as u16;
-                buffer[i+1] = low_surrogate as u16;
-                i += 2;
-            }
-        }
-
-        buffer[i] = 0;
-        let status = try!(self.output_string_utf16(&buffer[..i+1]));
-        if !status.is_success() {
-            stat = status
         }
 
         Ok(stat)
@@ -375,6 +394,30 @@ impl Protocol {
 
 }
 
+/// Iterator over the text modes of an output device, returned by `Protocol::modes()`.
+pub struct Modes<'a> {
+    protocol: &'a mut Protocol,
+    next: ModeNumber,
+    max_mode: ModeNumber,
+}
+
+impl<'a> Iterator for Modes<'a> {
+    type Item = (ModeNumber, Column, Row);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.next < self.max_mode {
+            let mode = self.next;
+            self.next += 1;
+
+            // Unsupported modes (e.g. mode 1 without 80x50) are skipped.
+            if let Ok((columns, rows)) = self.protocol.query_mode(mode) {
+                return Some((mode, columns, rows));
+            }
+        }
+        None
+    }
+}
+
 impl fmt::Write for Protocol {
     fn write_str(&mut self, s: &str) -> fmt::Result {
         match self.output_string(s) {
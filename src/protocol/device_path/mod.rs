@@ -0,0 +1,555 @@
+use core::borrow::Borrow;
+use core::fmt;
+use core::ops::Deref;
+use core::slice;
+
+use alloc::borrow::ToOwned;
+use alloc::vec::Vec;
+
+use protocol::Guid;
+use ucs2;
+
+mod node;
+mod text;
+mod to_text;
+
+pub use self::node::{Node, PartitionFormat, PartitionSignature, PathName, PathUnits};
+pub use self::text::ParseDevicePathError;
+pub use self::to_text::DevicePathToText;
+
+/// Node types.
+pub const HARDWARE_TYPE: u8 = 0x01;
+pub const ACPI_TYPE: u8 = 0x02;
+pub const MESSAGING_TYPE: u8 = 0x03;
+pub const MEDIA_TYPE: u8 = 0x04;
+pub const BBS_TYPE: u8 = 0x05;
+pub const END_TYPE: u8 = 0x7f;
+
+/// Sub-type of the node that terminates a whole device path.
+pub const END_ENTIRE_SUBTYPE: u8 = 0xff;
+/// Sub-type of the node that terminates one instance of a multi-instance device path.
+pub const END_INSTANCE_SUBTYPE: u8 = 0x01;
+
+/// Size of the header common to all nodes: type, sub-type and 16-bit length.
+pub const HEADER_SIZE: usize = 4;
+
+const END_NODE: [u8; 4] = [END_TYPE, END_ENTIRE_SUBTYPE, HEADER_SIZE as u8, 0];
+
+/// A device path, in the binary form used by the firmware
+/// (`EFI_DEVICE_PATH_PROTOCOL`), including the end node.
+pub struct DevicePath {
+    data: [u8],
+}
+
+/// The device path protocol interface, i.e. the first node of the device path
+/// of a device handle.
+///
+/// ```ignore
+/// let path = boot_services.open_protocol::<DevicePathProtocol>(handle, OpenProtocolAttributes::GetProtocol)?;
+/// let file = DevicePathBuilder::from_path(path.device_path().ok_or(Error::DEVICE_ERROR)?)
+///     .push_file_path("\\EFI\\Linux\\vmlinuz.efi")
+///     .finish();
+/// ```
+#[repr(C)]
+pub struct DevicePathProtocol {
+    node_type: u8,
+    sub_type: u8,
+    length: [u8; 2],
+}
+
+unsafe impl ::protocol::Protocol for DevicePathProtocol {
+    const GUID: Guid = Guid(0x09576e91,0x6d3f,0x11d2,[0x8e,0x39,0x00,0xa0,0xc9,0x69,0x72,0x3b]);
+}
+
+impl DevicePathProtocol {
+    /// The device path starting at this node, or `None` if the firmware's is malformed.
+    pub fn device_path(&self) -> Option<&DevicePath> {
+        unsafe { DevicePath::from_ptr(self as *const DevicePathProtocol as *const u8) }
+    }
+}
+
+/// A single node of a device path, not decoded.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct RawNode<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> RawNode<'a> {
+    pub fn node_type(&self) -> u8 {
+        self.bytes[0]
+    }
+
+    pub fn sub_type(&self) -> u8 {
+        self.bytes[1]
+    }
+
+    /// The node, without its header.
+    pub fn data(&self) -> &'a [u8] {
+        &self.bytes[HEADER_SIZE..]
+    }
+
+    /// The binary form of the node, including its header.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    pub fn is_end(&self) -> bool {
+        self.node_type() == END_TYPE && self.sub_type() == END_ENTIRE_SUBTYPE
+    }
+
+    pub fn parse(&self) -> Node<'a> {
+        Node::parse(*self)
+    }
+}
+
+impl<'a> fmt::Debug for RawNode<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "RawNode({:#04x}, {:#04x}, {:?})", self.node_type(), self.sub_type(), self.data())
+    }
+}
+
+/// Splits the first node off `data`, if it's well-formed.
+fn split_node(data: &[u8]) -> Option<(RawNode, &[u8])> {
+    if data.len() < HEADER_SIZE {
+        return None;
+    }
+    let len = (data[2] as usize) | (data[3] as usize) << 8;
+    if len < HEADER_SIZE || len > data.len() {
+        return None;
+    }
+    Some((RawNode{ bytes: &data[..len] }, &data[len..]))
+}
+
+/// Iterator over the nodes of a device path, without the end node.
+///
+/// Nodes that separate the instances of a multi-instance device path are included.
+pub struct RawNodes<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Iterator for RawNodes<'a> {
+    type Item = RawNode<'a>;
+
+    fn next(&mut self) -> Option<RawNode<'a>> {
+        match split_node(self.data) {
+            Some((node, rest)) if !node.is_end() => {
+                self.data = rest;
+                Some(node)
+            },
+            _ => {
+                self.data = &[];
+                None
+            },
+        }
+    }
+}
+
+/// Iterator over the decoded nodes of a device path, without the end node.
+pub struct Nodes<'a> {
+    raw: RawNodes<'a>,
+}
+
+impl<'a> Iterator for Nodes<'a> {
+    type Item = Node<'a>;
+
+    fn next(&mut self) -> Option<Node<'a>> {
+        self.raw.next().map(|raw| raw.parse())
+    }
+}
+
+impl DevicePath {
+    /// Checks that `data` is a sequence of well-formed nodes ending with an end node,
+    /// and returns the device path it contains.
+    ///
+    /// Anything after the end node is ignored.
+    pub fn from_bytes(data: &[u8]) -> Option<&DevicePath> {
+        let mut rest = data;
+        loop {
+            match split_node(rest) {
+                Some((node, tail)) => {
+                    rest = tail;
+                    if node.is_end() {
+                        let len = data.len() - rest.len();
+                        return Some(unsafe { DevicePath::from_bytes_unchecked(&data[..len]) });
+                    }
+                },
+                None => return None,
+            }
+        }
+    }
+
+    /// Same as `from_bytes()`, for data known to be well-formed and end exactly after the end node.
+    pub unsafe fn from_bytes_unchecked(data: &[u8]) -> &DevicePath {
+        &*(data as *const [u8] as *const DevicePath)
+    }
+
+    /// Wraps a device path provided by the firmware, or returns `None` if a node
+    /// before the end node is malformed.
+    ///
+    /// The device path must stay valid and unmodified for the lifetime `'a`.
+    pub unsafe fn from_ptr<'a>(p: *const u8) -> Option<&'a DevicePath> {
+        let mut offset = 0;
+        loop {
+            let node = p.offset(offset as isize);
+            let len = (*node.offset(2) as usize) | (*node.offset(3) as usize) << 8;
+            if len < HEADER_SIZE {
+                return None;
+            }
+            offset += len;
+            if *node == END_TYPE && *node.offset(1) == END_ENTIRE_SUBTYPE {
+                break;
+            }
+        }
+        Some(DevicePath::from_bytes_unchecked(slice::from_raw_parts(p, offset)))
+    }
+
+    /// Pointer to pass to the firmware.
+    pub fn as_ptr(&self) -> *const u8 {
+        self.data.as_ptr()
+    }
+
+    /// The binary form of the device path, including the end node.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Size of the device path in bytes, including the end node.
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Whether the device path has no nodes other than the end node.
+    pub fn is_empty(&self) -> bool {
+        self.raw_nodes().next().is_none()
+    }
+
+    pub fn raw_nodes(&self) -> RawNodes {
+        RawNodes{ data: &self.data }
+    }
+
+    pub fn nodes(&self) -> Nodes {
+        Nodes{ raw: self.raw_nodes() }
+    }
+
+    /// Whether `prefix` is a path to the same device or one of its parents,
+    /// e.g. whether a `HardDrive` node is on a given disk.
+    pub fn starts_with(&self, prefix: &DevicePath) -> bool {
+        let prefix = &prefix.data[..prefix.data.len() - end_node_size(prefix)];
+        self.data.starts_with(prefix)
+    }
+}
+
+/// Size of the end node at the end of a well-formed path.
+fn end_node_size(path: &DevicePath) -> usize {
+    let len: usize = path.raw_nodes().map(|node| node.as_bytes().len()).sum();
+    path.data.len() - len
+}
+
+impl PartialEq for DevicePath {
+    fn eq(&self, other: &DevicePath) -> bool {
+        self.data == other.data
+    }
+}
+
+impl Eq for DevicePath {}
+
+impl fmt::Debug for DevicePath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.nodes()).finish()
+    }
+}
+
+impl AsRef<DevicePath> for DevicePath {
+    fn as_ref(&self) -> &DevicePath {
+        self
+    }
+}
+
+impl ToOwned for DevicePath {
+    type Owned = DevicePathBuf;
+
+    fn to_owned(&self) -> DevicePathBuf {
+        DevicePathBuf{ data: self.data.to_vec() }
+    }
+}
+
+/// Owned device path, e.g. built with `DevicePathBuilder`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct DevicePathBuf {
+    data: Vec<u8>,
+}
+
+impl DevicePathBuf {
+    /// Copies and validates a device path in binary form, as with `DevicePath::from_bytes()`.
+    pub fn from_bytes(data: &[u8]) -> Option<DevicePathBuf> {
+        DevicePath::from_bytes(data).map(|path| path.to_owned())
+    }
+
+    pub fn as_device_path(&self) -> &DevicePath {
+        unsafe { DevicePath::from_bytes_unchecked(&self.data) }
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+impl Deref for DevicePathBuf {
+    type Target = DevicePath;
+
+    fn deref(&self) -> &DevicePath {
+        self.as_device_path()
+    }
+}
+
+impl AsRef<DevicePath> for DevicePathBuf {
+    fn as_ref(&self) -> &DevicePath {
+        self.as_device_path()
+    }
+}
+
+impl Borrow<DevicePath> for DevicePathBuf {
+    fn borrow(&self) -> &DevicePath {
+        self.as_device_path()
+    }
+}
+
+impl<'a> From<&'a DevicePath> for DevicePathBuf {
+    fn from(path: &'a DevicePath) -> DevicePathBuf {
+        path.to_owned()
+    }
+}
+
+impl fmt::Debug for DevicePathBuf {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_device_path(), f)
+    }
+}
+
+/// Builds a device path node by node.
+///
+/// ```ignore
+/// let path = DevicePathBuilder::new()
+///     .push(&Node::Acpi{ hid: 0x0a0341d0, uid: 0 })
+///     .push(&Node::Pci{ function: 0, device: 0x1f })
+///     .finish();
+/// ```
+#[derive(Clone)]
+pub struct DevicePathBuilder {
+    data: Vec<u8>,
+}
+
+impl DevicePathBuilder {
+    pub fn new() -> DevicePathBuilder {
+        DevicePathBuilder{ data: Vec::new() }
+    }
+
+    /// Starts with the nodes of `path`, to append to it.
+    pub fn from_path(path: &DevicePath) -> DevicePathBuilder {
+        let mut builder = DevicePathBuilder::new();
+        builder.append(path);
+        builder
+    }
+
+    /// Appends a node.
+    ///
+    /// Panics if the node is longer than the 64 KiB a node can hold.
+    pub fn push(&mut self, node: &Node) -> &mut DevicePathBuilder {
+        node.encode(&mut self.data);
+        self
+    }
+
+    /// Appends an undecoded node, e.g. one taken from another path.
+    pub fn push_raw(&mut self, node: RawNode) -> &mut DevicePathBuilder {
+        self.data.extend_from_slice(node.as_bytes());
+        self
+    }
+
+    /// Appends a `FilePath` node, as used to load an image from a volume.
+    ///
+    /// Panics if the path is longer than the 64 KiB a node can hold.
+    pub fn push_file_path(&mut self, path: &str) -> &mut DevicePathBuilder {
+        let mut bytes = Vec::new();
+        for unit in ucs2::Encoder::new(path, ucs2::Policy::Surrogates).chain(Some(0)) {
+            bytes.push(unit as u8);
+            bytes.push((unit >> 8) as u8);
+        }
+        self.push(&Node::FilePath{ path: PathName::from_bytes(&bytes) })
+    }
+
+    /// Appends all nodes of `path`, except its end node.
+    pub fn append(&mut self, path: &DevicePath) -> &mut DevicePathBuilder {
+        for node in path.raw_nodes() {
+            self.push_raw(node);
+        }
+        self
+    }
+
+    /// Ends the current instance of a multi-instance device path.
+    pub fn end_instance(&mut self) -> &mut DevicePathBuilder {
+        self.push(&Node::EndInstance)
+    }
+
+    /// Adds the end node, and returns the device path.
+    pub fn finish(&self) -> DevicePathBuf {
+        let mut data = Vec::with_capacity(self.data.len() + HEADER_SIZE);
+        data.extend_from_slice(&self.data);
+        data.extend_from_slice(&END_NODE);
+        DevicePathBuf{ data: data }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use protocol::Guid;
+    use super::*;
+
+    /// `PciRoot(0x0)/Pci(0x1f,0x2)/Sata(0x0,0xffff,0x0)/HD(1,GPT,...)/\EFI\BOOT\BOOTX64.EFI`,
+    /// the removable media boot entry OVMF creates for a SATA disk on QEMU's q35 machine.
+    pub(super) fn ovmf_sata() -> Vec<u8> {
+        let mut path = Vec::new();
+        path.extend_from_slice(&[0x02, 0x01, 0x0c, 0x00, 0xd0, 0x41, 0x03, 0x0a, 0x00, 0x00, 0x00, 0x00]);
+        path.extend_from_slice(&[0x01, 0x01, 0x06, 0x00, 0x02, 0x1f]);
+        path.extend_from_slice(&[0x03, 0x12, 0x0a, 0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00]);
+        path.extend_from_slice(&[0x04, 0x01, 0x2a, 0x00, 0x01, 0x00, 0x00, 0x00,
+                                 0x00, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                                 0x00, 0x20, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00,
+                                 0xf5, 0x9d, 0x0f, 0x3e, 0x5b, 0xde, 0x84, 0x4c,
+                                 0x9e, 0x0f, 0x5a, 0x9a, 0x2b, 0x1e, 0x8d, 0x7c, 0x02, 0x02]);
+        path.extend_from_slice(&[0x04, 0x04, 0x30, 0x00]);
+        path.extend_from_slice(b"\\\0E\0F\0I\0\\\0B\0O\0O\0T\0\\\0B\0O\0O\0T\0X\06\04\0.\0E\0F\0I\0\0\0");
+        path.extend_from_slice(&END_NODE);
+        path
+    }
+
+    /// `PciRoot(0x0)/Pci(0x3,0x0)/MAC(525400123456,0x1)/IPv4(0.0.0.0,...)`,
+    /// the PXE boot entry OVMF creates for a virtio-net card.
+    pub(super) fn ovmf_pxe() -> Vec<u8> {
+        let mut path = Vec::new();
+        path.extend_from_slice(&[0x02, 0x01, 0x0c, 0x00, 0xd0, 0x41, 0x03, 0x0a, 0x00, 0x00, 0x00, 0x00]);
+        path.extend_from_slice(&[0x01, 0x01, 0x06, 0x00, 0x00, 0x03]);
+        path.extend_from_slice(&[0x03, 0x0b, 0x25, 0x00, 0x52, 0x54, 0x00, 0x12, 0x34, 0x56]);
+        path.extend_from_slice(&[0; 26]);
+        path.push(0x01);
+        path.extend_from_slice(&[0x03, 0x0c, 0x1b, 0x00]);
+        path.extend_from_slice(&[0; 23]);
+        path.extend_from_slice(&END_NODE);
+        path
+    }
+
+    /// A UEFI 2.2 IPv4 node, without gateway and subnet mask:
+    /// 192.168.0.2:68 to 192.168.0.1:67 over UDP.
+    pub(super) fn legacy_ipv4() -> Vec<u8> {
+        let mut path = Vec::new();
+        path.extend_from_slice(&[0x03, 0x0c, 0x13, 0x00, 192, 168, 0, 2, 192, 168, 0, 1,
+                                 0x44, 0x00, 0x43, 0x00, 0x11, 0x00, 0x00]);
+        path.extend_from_slice(&END_NODE);
+        path
+    }
+
+    /// A UEFI 2.2 IPv6 node, without prefix length and gateway.
+    pub(super) fn legacy_ipv6() -> Vec<u8> {
+        let mut path = Vec::new();
+        path.extend_from_slice(&[0x03, 0x0d, 0x2b, 0x00]);
+        path.extend_from_slice(&[0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0x50, 0x54, 0, 0xff, 0xfe, 0x12, 0x34, 0x56]);
+        path.extend_from_slice(&[0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01]);
+        path.extend_from_slice(&[0x22, 0x02, 0x23, 0x02, 0x11, 0x00, 0x01]);
+        path.extend_from_slice(&END_NODE);
+        path
+    }
+
+    /// Checks that every node, and the whole path, encodes back to the same bytes.
+    fn assert_round_trip(bytes: &[u8]) {
+        let path = DevicePath::from_bytes(bytes).unwrap();
+        let mut builder = DevicePathBuilder::new();
+        for raw in path.raw_nodes() {
+            let mut encoded = Vec::new();
+            raw.parse().encode(&mut encoded);
+            assert_eq!(&encoded[..], raw.as_bytes());
+            builder.push(&raw.parse());
+        }
+        assert_eq!(builder.finish().as_bytes(), bytes);
+    }
+
+    #[test]
+    fn sata_path() {
+        let bytes = ovmf_sata();
+        let path = DevicePath::from_bytes(&bytes).unwrap();
+        assert_eq!(path.len(), bytes.len());
+
+        let nodes: Vec<Node> = path.nodes().collect();
+        assert_eq!(nodes.len(), 5);
+        assert_eq!(nodes[0], Node::Acpi{ hid: 0x0a0341d0, uid: 0 });
+        assert_eq!(nodes[1], Node::Pci{ function: 2, device: 0x1f });
+        assert_eq!(nodes[2], Node::Sata{ hba_port: 0, port_multiplier_port: 0xffff, lun: 0 });
+        let guid = Guid(0x3e0f9df5, 0xde5b, 0x4c84, [0x9e, 0x0f, 0x5a, 0x9a, 0x2b, 0x1e, 0x8d, 0x7c]);
+        assert_eq!(nodes[3], Node::HardDrive{ partition_number: 1, partition_start: 0x800, partition_size: 0x32000,
+                                              format: PartitionFormat::Gpt,
+                                              signature: PartitionSignature::Guid(guid) });
+        match nodes[4] {
+            Node::FilePath{ path } => assert_eq!(path.to_string(), "\\EFI\\BOOT\\BOOTX64.EFI"),
+            ref other => panic!("{:?}", other),
+        }
+
+        assert_round_trip(&bytes);
+    }
+
+    #[test]
+    fn pxe_path() {
+        let bytes = ovmf_pxe();
+        let nodes: Vec<Node> = DevicePath::from_bytes(&bytes).unwrap().nodes().collect();
+        assert_eq!(nodes.len(), 4);
+        assert_eq!(nodes[1], Node::Pci{ function: 0, device: 3 });
+        let mut address = [0; 32];
+        address[..6].copy_from_slice(&[0x52, 0x54, 0x00, 0x12, 0x34, 0x56]);
+        assert_eq!(nodes[2], Node::MacAddress{ address: address, if_type: 1 });
+        assert_eq!(nodes[3], Node::Ipv4{ local: [0; 4], remote: [0; 4], local_port: 0, remote_port: 0, protocol: 0,
+                                         static_address: false, gateway: Some([0; 4]), subnet_mask: Some([0; 4]) });
+
+        assert_round_trip(&bytes);
+    }
+
+    #[test]
+    fn legacy_ip_nodes_keep_their_size() {
+        let bytes = legacy_ipv4();
+        let node = DevicePath::from_bytes(&bytes).unwrap().nodes().next().unwrap();
+        assert_eq!(node, Node::Ipv4{ local: [192, 168, 0, 2], remote: [192, 168, 0, 1], local_port: 68,
+                                     remote_port: 67, protocol: 17, static_address: false,
+                                     gateway: None, subnet_mask: None });
+        assert_round_trip(&bytes);
+
+        let bytes = legacy_ipv6();
+        match DevicePath::from_bytes(&bytes).unwrap().nodes().next().unwrap() {
+            Node::Ipv6{ local_port: 546, remote_port: 547, protocol: 17, origin: 1,
+                        prefix_length: None, gateway: None, .. } => {},
+            other => panic!("{:?}", other),
+        }
+        assert_round_trip(&bytes);
+    }
+
+    #[test]
+    fn from_ptr_needs_well_formed_nodes() {
+        let bytes = ovmf_sata();
+        let path = unsafe { DevicePath::from_ptr(bytes.as_ptr()) }.unwrap();
+        assert_eq!(path.as_bytes(), &bytes[..]);
+
+        // A zero-length node before the end node.
+        let mut bytes = ovmf_pxe();
+        bytes[12 + 2] = 0;
+        assert!(unsafe { DevicePath::from_ptr(bytes.as_ptr()) }.is_none());
+        assert!(DevicePath::from_bytes(&bytes).is_none());
+    }
+
+    #[test]
+    fn file_path_outside_bmp() {
+        let path = DevicePathBuilder::new().push_file_path("\\\u{1f600}.efi").finish();
+        match path.nodes().next().unwrap() {
+            Node::FilePath{ path } => {
+                assert_eq!(&path.as_bytes()[2..6], &[0x3d, 0xd8, 0x00, 0xde]);
+                assert_eq!(path.to_string(), "\\\u{1f600}.efi");
+            },
+            other => panic!("{:?}", other),
+        }
+    }
+}
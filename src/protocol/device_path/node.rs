@@ -0,0 +1,580 @@
+use core::str;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use protocol::Guid;
+use protocol::bytes::{read_u16, read_u32, read_u64, write_u16, write_u32, write_u64};
+use ucs2;
+use super::{ACPI_TYPE, END_ENTIRE_SUBTYPE, END_INSTANCE_SUBTYPE, END_TYPE, HARDWARE_TYPE, HEADER_SIZE,
+            MEDIA_TYPE, MESSAGING_TYPE, RawNode};
+
+// Hardware sub-types.
+const PCI: u8 = 1;
+const PCCARD: u8 = 2;
+const MEMORY_MAPPED: u8 = 3;
+const VENDOR_HARDWARE: u8 = 4;
+const CONTROLLER: u8 = 5;
+const BMC: u8 = 6;
+
+// ACPI sub-types.
+const ACPI: u8 = 1;
+const EXPANDED_ACPI: u8 = 2;
+const ADR: u8 = 3;
+
+// Messaging sub-types.
+const ATAPI: u8 = 1;
+const SCSI: u8 = 2;
+const USB: u8 = 5;
+const VENDOR_MESSAGING: u8 = 10;
+const MAC_ADDRESS: u8 = 11;
+const IPV4: u8 = 12;
+const IPV6: u8 = 13;
+const USB_CLASS: u8 = 15;
+const SATA: u8 = 18;
+const NVME: u8 = 23;
+const URI: u8 = 24;
+
+// Media sub-types.
+const HARD_DRIVE: u8 = 1;
+const CD_ROM: u8 = 2;
+const VENDOR_MEDIA: u8 = 3;
+const FILE_PATH: u8 = 4;
+const MEDIA_PROTOCOL: u8 = 5;
+const FIRMWARE_FILE: u8 = 6;
+const FIRMWARE_VOLUME: u8 = 7;
+const RELATIVE_OFFSET_RANGE: u8 = 8;
+
+/// Partition table a `Node::HardDrive` partition belongs to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PartitionFormat {
+    Mbr,
+    Gpt,
+    Other(u8),
+}
+
+impl From<u8> for PartitionFormat {
+    fn from(value: u8) -> PartitionFormat {
+        match value {
+            1 => PartitionFormat::Mbr,
+            2 => PartitionFormat::Gpt,
+            other => PartitionFormat::Other(other),
+        }
+    }
+}
+
+impl From<PartitionFormat> for u8 {
+    fn from(format: PartitionFormat) -> u8 {
+        match format {
+            PartitionFormat::Mbr => 1,
+            PartitionFormat::Gpt => 2,
+            PartitionFormat::Other(other) => other,
+        }
+    }
+}
+
+/// Identifies the disk or partition of a `Node::HardDrive`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PartitionSignature {
+    None,
+    /// Disk signature at offset 0x1b8 of the MBR.
+    Mbr(u32),
+    /// Unique partition GUID from the GPT partition entry.
+    Guid(Guid),
+    Other { signature_type: u8, signature: [u8; 16] },
+}
+
+/// A NUL-terminated UCS-2 path stored in a device path node.
+///
+/// The node data has no alignment, so the path is kept as bytes.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct PathName<'a> {
+    bytes: &'a [u8],
+}
+
+/// A device path node, decoded.
+///
+/// Nodes this crate doesn't know, and known nodes with an unexpected length,
+/// are returned as `Unknown`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Node<'a> {
+    Pci { function: u8, device: u8 },
+    PcCard { function: u8 },
+    MemoryMapped { memory_type: u32, start: u64, end: u64 },
+    VendorHardware { guid: Guid, data: &'a [u8] },
+    Controller { number: u32 },
+    Bmc { interface_type: u8, base_address: u64 },
+
+    Acpi { hid: u32, uid: u32 },
+    ExpandedAcpi { hid: u32, uid: u32, cid: u32, hid_str: &'a str, uid_str: &'a str, cid_str: &'a str },
+    Adr { adr: u32 },
+
+    Atapi { secondary: bool, slave: bool, lun: u16 },
+    Scsi { target: u16, lun: u16 },
+    Usb { parent_port: u8, interface: u8 },
+    VendorMessaging { guid: Guid, data: &'a [u8] },
+    MacAddress { address: [u8; 32], if_type: u8 },
+    Ipv4 {
+        local: [u8; 4],
+        remote: [u8; 4],
+        local_port: u16,
+        remote_port: u16,
+        protocol: u16,
+        static_address: bool,
+        /// `None` in nodes from before UEFI 2.3, which end after `static_address`.
+        gateway: Option<[u8; 4]>,
+        /// `None` in nodes from before UEFI 2.3. Encoded as zero if only `gateway` is set.
+        subnet_mask: Option<[u8; 4]>,
+    },
+    Ipv6 {
+        local: [u8; 16],
+        remote: [u8; 16],
+        local_port: u16,
+        remote_port: u16,
+        protocol: u16,
+        origin: u8,
+        /// `None` in nodes from before UEFI 2.3, which end after `origin`.
+        prefix_length: Option<u8>,
+        /// `None` in nodes from before UEFI 2.3. Encoded as zero if only `prefix_length` is set.
+        gateway: Option<[u8; 16]>,
+    },
+    UsbClass { vendor_id: u16, product_id: u16, class: u8, subclass: u8, protocol: u8 },
+    Sata { hba_port: u16, port_multiplier_port: u16, lun: u16 },
+    Nvme { namespace_id: u32, eui64: [u8; 8] },
+    Uri { uri: &'a [u8] },
+
+    HardDrive {
+        partition_number: u32,
+        partition_start: u64,
+        partition_size: u64,
+        format: PartitionFormat,
+        signature: PartitionSignature,
+    },
+    CdRom { boot_entry: u32, partition_start: u64, partition_size: u64 },
+    VendorMedia { guid: Guid, data: &'a [u8] },
+    FilePath { path: PathName<'a> },
+    MediaProtocol { protocol: Guid },
+    FirmwareFile { name: Guid },
+    FirmwareVolume { name: Guid },
+    RelativeOffsetRange { start: u64, end: u64 },
+
+    /// Separates the instances of a multi-instance device path.
+    EndInstance,
+    /// Terminates the device path.
+    End,
+
+    Unknown { node_type: u8, sub_type: u8, data: &'a [u8] },
+}
+
+fn read_guid(d: &[u8], o: usize) -> Guid {
+    let mut b = [0; 16];
+    b.copy_from_slice(&d[o..o+16]);
+    Guid::from_bytes(b)
+}
+
+/// Splits a NUL-terminated ASCII string off the front of `d`.
+fn read_str(d: &[u8]) -> Option<(&str, &[u8])> {
+    let len = match d.iter().position(|&c| c == 0) {
+        Some(len) => len,
+        None => return None,
+    };
+    match str::from_utf8(&d[..len]) {
+        Ok(s) => Some((s, &d[len+1..])),
+        Err(_) => None,
+    }
+}
+
+fn parse_expanded_acpi(d: &[u8]) -> Option<Node> {
+    let mut strs = [""; 3];
+    let mut rest = &d[12..];
+    for s in strs.iter_mut() {
+        match read_str(rest) {
+            Some((first, tail)) => {
+                *s = first;
+                rest = tail;
+            },
+            None => return None,
+        }
+    }
+    if !rest.is_empty() {
+        return None;
+    }
+
+    Some(Node::ExpandedAcpi {
+        hid: read_u32(d, 0),
+        uid: read_u32(d, 4),
+        cid: read_u32(d, 8),
+        hid_str: strs[0],
+        uid_str: strs[1],
+        cid_str: strs[2],
+    })
+}
+
+impl<'a> Node<'a> {
+    /// Decodes a node.
+    pub fn parse(raw: RawNode<'a>) -> Node<'a> {
+        Node::parse_known(raw).unwrap_or(Node::Unknown {
+            node_type: raw.node_type(),
+            sub_type: raw.sub_type(),
+            data: raw.data(),
+        })
+    }
+
+    fn parse_known(raw: RawNode<'a>) -> Option<Node<'a>> {
+        let d = raw.data();
+        let len = d.len();
+
+        let node = match (raw.node_type(), raw.sub_type()) {
+            (HARDWARE_TYPE, PCI) if len == 2 => Node::Pci{ function: d[0], device: d[1] },
+            (HARDWARE_TYPE, PCCARD) if len == 1 => Node::PcCard{ function: d[0] },
+            (HARDWARE_TYPE, MEMORY_MAPPED) if len == 20 =>
+                Node::MemoryMapped{ memory_type: read_u32(d, 0), start: read_u64(d, 4), end: read_u64(d, 12) },
+            (HARDWARE_TYPE, VENDOR_HARDWARE) if len >= 16 =>
+                Node::VendorHardware{ guid: read_guid(d, 0), data: &d[16..] },
+            (HARDWARE_TYPE, CONTROLLER) if len == 4 => Node::Controller{ number: read_u32(d, 0) },
+            (HARDWARE_TYPE, BMC) if len == 9 => Node::Bmc{ interface_type: d[0], base_address: read_u64(d, 1) },
+
+            (ACPI_TYPE, ACPI) if len == 8 => Node::Acpi{ hid: read_u32(d, 0), uid: read_u32(d, 4) },
+            (ACPI_TYPE, EXPANDED_ACPI) if len >= 15 => return parse_expanded_acpi(d),
+            (ACPI_TYPE, ADR) if len == 4 => Node::Adr{ adr: read_u32(d, 0) },
+
+            (MESSAGING_TYPE, ATAPI) if len == 4 =>
+                Node::Atapi{ secondary: d[0] != 0, slave: d[1] != 0, lun: read_u16(d, 2) },
+            (MESSAGING_TYPE, SCSI) if len == 4 => Node::Scsi{ target: read_u16(d, 0), lun: read_u16(d, 2) },
+            (MESSAGING_TYPE, USB) if len == 2 => Node::Usb{ parent_port: d[0], interface: d[1] },
+            (MESSAGING_TYPE, VENDOR_MESSAGING) if len >= 16 =>
+                Node::VendorMessaging{ guid: read_guid(d, 0), data: &d[16..] },
+            (MESSAGING_TYPE, MAC_ADDRESS) if len == 33 => {
+                let mut address = [0; 32];
+                address.copy_from_slice(&d[..32]);
+                Node::MacAddress{ address: address, if_type: d[32] }
+            },
+            // Before UEFI 2.3 the node ended after `static_address`.
+            (MESSAGING_TYPE, IPV4) if len == 15 || len == 23 => {
+                let mut ip = [[0; 4]; 4];
+                ip[0].copy_from_slice(&d[0..4]);
+                ip[1].copy_from_slice(&d[4..8]);
+                let extended = len == 23;
+                if extended {
+                    ip[2].copy_from_slice(&d[15..19]);
+                    ip[3].copy_from_slice(&d[19..23]);
+                }
+                Node::Ipv4 {
+                    local: ip[0],
+                    remote: ip[1],
+                    local_port: read_u16(d, 8),
+                    remote_port: read_u16(d, 10),
+                    protocol: read_u16(d, 12),
+                    static_address: d[14] != 0,
+                    gateway: if extended { Some(ip[2]) } else { None },
+                    subnet_mask: if extended { Some(ip[3]) } else { None },
+                }
+            },
+            // Before UEFI 2.3 the node ended after `origin`, which was a boolean then.
+            (MESSAGING_TYPE, IPV6) if len == 39 || len == 56 => {
+                let mut ip = [[0; 16]; 3];
+                ip[0].copy_from_slice(&d[0..16]);
+                ip[1].copy_from_slice(&d[16..32]);
+                let prefix_length = if len == 56 {
+                    ip[2].copy_from_slice(&d[40..56]);
+                    Some(d[39])
+                } else {
+                    None
+                };
+                Node::Ipv6 {
+                    local: ip[0],
+                    remote: ip[1],
+                    local_port: read_u16(d, 32),
+                    remote_port: read_u16(d, 34),
+                    protocol: read_u16(d, 36),
+                    origin: d[38],
+                    prefix_length: prefix_length,
+                    gateway: prefix_length.map(|_| ip[2]),
+                }
+            },
+            (MESSAGING_TYPE, USB_CLASS) if len == 7 => Node::UsbClass {
+                vendor_id: read_u16(d, 0),
+                product_id: read_u16(d, 2),
+                class: d[4],
+                subclass: d[5],
+                protocol: d[6],
+            },
+            (MESSAGING_TYPE, SATA) if len == 6 =>
+                Node::Sata{ hba_port: read_u16(d, 0), port_multiplier_port: read_u16(d, 2), lun: read_u16(d, 4) },
+            (MESSAGING_TYPE, NVME) if len == 12 => {
+                let mut eui64 = [0; 8];
+                eui64.copy_from_slice(&d[4..12]);
+                Node::Nvme{ namespace_id: read_u32(d, 0), eui64: eui64 }
+            },
+            (MESSAGING_TYPE, URI) => Node::Uri{ uri: d },
+
+            (MEDIA_TYPE, HARD_DRIVE) if len == 38 => {
+                let format = PartitionFormat::from(d[36]);
+                let signature = match d[37] {
+                    0 => PartitionSignature::None,
+                    1 => PartitionSignature::Mbr(read_u32(d, 20)),
+                    2 => PartitionSignature::Guid(read_guid(d, 20)),
+                    other => {
+                        let mut signature = [0; 16];
+                        signature.copy_from_slice(&d[20..36]);
+                        PartitionSignature::Other{ signature_type: other, signature: signature }
+                    },
+                };
+                Node::HardDrive {
+                    partition_number: read_u32(d, 0),
+                    partition_start: read_u64(d, 4),
+                    partition_size: read_u64(d, 12),
+                    format: format,
+                    signature: signature,
+                }
+            },
+            (MEDIA_TYPE, CD_ROM) if len == 20 => Node::CdRom {
+                boot_entry: read_u32(d, 0),
+                partition_start: read_u64(d, 4),
+                partition_size: read_u64(d, 12),
+            },
+            (MEDIA_TYPE, VENDOR_MEDIA) if len >= 16 => Node::VendorMedia{ guid: read_guid(d, 0), data: &d[16..] },
+            (MEDIA_TYPE, FILE_PATH) if len % 2 == 0 => Node::FilePath{ path: PathName{ bytes: d } },
+            (MEDIA_TYPE, MEDIA_PROTOCOL) if len == 16 => Node::MediaProtocol{ protocol: read_guid(d, 0) },
+            (MEDIA_TYPE, FIRMWARE_FILE) if len == 16 => Node::FirmwareFile{ name: read_guid(d, 0) },
+            (MEDIA_TYPE, FIRMWARE_VOLUME) if len == 16 => Node::FirmwareVolume{ name: read_guid(d, 0) },
+            (MEDIA_TYPE, RELATIVE_OFFSET_RANGE) if len == 20 =>
+                Node::RelativeOffsetRange{ start: read_u64(d, 4), end: read_u64(d, 12) },
+
+            (END_TYPE, END_INSTANCE_SUBTYPE) => Node::EndInstance,
+            (END_TYPE, END_ENTIRE_SUBTYPE) => Node::End,
+
+            _ => return None,
+        };
+        Some(node)
+    }
+
+    /// Type and sub-type of the node.
+    pub fn node_type(&self) -> (u8, u8) {
+        match *self {
+            Node::Pci{..} => (HARDWARE_TYPE, PCI),
+            Node::PcCard{..} => (HARDWARE_TYPE, PCCARD),
+            Node::MemoryMapped{..} => (HARDWARE_TYPE, MEMORY_MAPPED),
+            Node::VendorHardware{..} => (HARDWARE_TYPE, VENDOR_HARDWARE),
+            Node::Controller{..} => (HARDWARE_TYPE, CONTROLLER),
+            Node::Bmc{..} => (HARDWARE_TYPE, BMC),
+            Node::Acpi{..} => (ACPI_TYPE, ACPI),
+            Node::ExpandedAcpi{..} => (ACPI_TYPE, EXPANDED_ACPI),
+            Node::Adr{..} => (ACPI_TYPE, ADR),
+            Node::Atapi{..} => (MESSAGING_TYPE, ATAPI),
+            Node::Scsi{..} => (MESSAGING_TYPE, SCSI),
+            Node::Usb{..} => (MESSAGING_TYPE, USB),
+            Node::VendorMessaging{..} => (MESSAGING_TYPE, VENDOR_MESSAGING),
+            Node::MacAddress{..} => (MESSAGING_TYPE, MAC_ADDRESS),
+            Node::Ipv4{..} => (MESSAGING_TYPE, IPV4),
+            Node::Ipv6{..} => (MESSAGING_TYPE, IPV6),
+            Node::UsbClass{..} => (MESSAGING_TYPE, USB_CLASS),
+            Node::Sata{..} => (MESSAGING_TYPE, SATA),
+            Node::Nvme{..} => (MESSAGING_TYPE, NVME),
+            Node::Uri{..} => (MESSAGING_TYPE, URI),
+            Node::HardDrive{..} => (MEDIA_TYPE, HARD_DRIVE),
+            Node::CdRom{..} => (MEDIA_TYPE, CD_ROM),
+            Node::VendorMedia{..} => (MEDIA_TYPE, VENDOR_MEDIA),
+            Node::FilePath{..} => (MEDIA_TYPE, FILE_PATH),
+            Node::MediaProtocol{..} => (MEDIA_TYPE, MEDIA_PROTOCOL),
+            Node::FirmwareFile{..} => (MEDIA_TYPE, FIRMWARE_FILE),
+            Node::FirmwareVolume{..} => (MEDIA_TYPE, FIRMWARE_VOLUME),
+            Node::RelativeOffsetRange{..} => (MEDIA_TYPE, RELATIVE_OFFSET_RANGE),
+            Node::EndInstance => (END_TYPE, END_INSTANCE_SUBTYPE),
+            Node::End => (END_TYPE, END_ENTIRE_SUBTYPE),
+            Node::Unknown{ node_type, sub_type, .. } => (node_type, sub_type),
+        }
+    }
+
+    /// Appends the binary form of the node, including its header, to `out`.
+    ///
+    /// Panics if the node is longer than the 64 KiB a node can hold.
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        let start = out.len();
+        let (node_type, sub_type) = self.node_type();
+        out.extend_from_slice(&[node_type, sub_type, 0, 0]);
+
+        match *self {
+            Node::Pci{ function, device } => out.extend_from_slice(&[function, device]),
+            Node::PcCard{ function } => out.push(function),
+            Node::MemoryMapped{ memory_type, start, end } => {
+                write_u32(out, memory_type);
+                write_u64(out, start);
+                write_u64(out, end);
+            },
+            Node::VendorHardware{ guid, data } |
+            Node::VendorMessaging{ guid, data } |
+            Node::VendorMedia{ guid, data } => {
+                out.extend_from_slice(&guid.to_bytes());
+                out.extend_from_slice(data);
+            },
+            Node::Controller{ number } => write_u32(out, number),
+            Node::Bmc{ interface_type, base_address } => {
+                out.push(interface_type);
+                write_u64(out, base_address);
+            },
+            Node::Acpi{ hid, uid } => {
+                write_u32(out, hid);
+                write_u32(out, uid);
+            },
+            Node::ExpandedAcpi{ hid, uid, cid, hid_str, uid_str, cid_str } => {
+                write_u32(out, hid);
+                write_u32(out, uid);
+                write_u32(out, cid);
+                for s in &[hid_str, uid_str, cid_str] {
+                    out.extend_from_slice(s.as_bytes());
+                    out.push(0);
+                }
+            },
+            Node::Adr{ adr } => write_u32(out, adr),
+            Node::Atapi{ secondary, slave, lun } => {
+                out.extend_from_slice(&[secondary as u8, slave as u8]);
+                write_u16(out, lun);
+            },
+            Node::Scsi{ target, lun } => {
+                write_u16(out, target);
+                write_u16(out, lun);
+            },
+            Node::Usb{ parent_port, interface } => out.extend_from_slice(&[parent_port, interface]),
+            Node::MacAddress{ ref address, if_type } => {
+                out.extend_from_slice(address);
+                out.push(if_type);
+            },
+            Node::Ipv4{ ref local, ref remote, local_port, remote_port, protocol, static_address,
+                        gateway, subnet_mask } => {
+                out.extend_from_slice(local);
+                out.extend_from_slice(remote);
+                write_u16(out, local_port);
+                write_u16(out, remote_port);
+                write_u16(out, protocol);
+                out.push(static_address as u8);
+                if gateway.is_some() || subnet_mask.is_some() {
+                    out.extend_from_slice(&gateway.unwrap_or([0; 4]));
+                    out.extend_from_slice(&subnet_mask.unwrap_or([0; 4]));
+                }
+            },
+            Node::Ipv6{ ref local, ref remote, local_port, remote_port, protocol, origin,
+                        prefix_length, gateway } => {
+                out.extend_from_slice(local);
+                out.extend_from_slice(remote);
+                write_u16(out, local_port);
+                write_u16(out, remote_port);
+                write_u16(out, protocol);
+                out.push(origin);
+                if prefix_length.is_some() || gateway.is_some() {
+                    out.push(prefix_length.unwrap_or(0));
+                    out.extend_from_slice(&gateway.unwrap_or([0; 16]));
+                }
+            },
+            Node::UsbClass{ vendor_id, product_id, class, subclass, protocol } => {
+                write_u16(out, vendor_id);
+                write_u16(out, product_id);
+                out.extend_from_slice(&[class, subclass, protocol]);
+            },
+            Node::Sata{ hba_port, port_multiplier_port, lun } => {
+                write_u16(out, hba_port);
+                write_u16(out, port_multiplier_port);
+                write_u16(out, lun);
+            },
+            Node::Nvme{ namespace_id, ref eui64 } => {
+                write_u32(out, namespace_id);
+                out.extend_from_slice(eui64);
+            },
+            Node::Uri{ uri } => out.extend_from_slice(uri),
+            Node::HardDrive{ partition_number, partition_start, partition_size, format, signature } => {
+                write_u32(out, partition_number);
+                write_u64(out, partition_start);
+                write_u64(out, partition_size);
+                let (signature_type, bytes) = match signature {
+                    PartitionSignature::None => (0, [0; 16]),
+                    PartitionSignature::Mbr(sig) => {
+                        let mut bytes = [0; 16];
+                        for i in 0..4 {
+                            bytes[i] = (sig >> (i * 8)) as u8;
+                        }
+                        (1, bytes)
+                    },
+                    PartitionSignature::Guid(guid) => (2, guid.to_bytes()),
+                    PartitionSignature::Other{ signature_type, signature } => (signature_type, signature),
+                };
+                out.extend_from_slice(&bytes);
+                out.push(u8::from(format));
+                out.push(signature_type);
+            },
+            Node::CdRom{ boot_entry, partition_start, partition_size } => {
+                write_u32(out, boot_entry);
+                write_u64(out, partition_start);
+                write_u64(out, partition_size);
+            },
+            Node::FilePath{ path } => out.extend_from_slice(path.bytes),
+            Node::MediaProtocol{ protocol: guid } |
+            Node::FirmwareFile{ name: guid } |
+            Node::FirmwareVolume{ name: guid } => out.extend_from_slice(&guid.to_bytes()),
+            Node::RelativeOffsetRange{ start, end } => {
+                write_u32(out, 0);
+                write_u64(out, start);
+                write_u64(out, end);
+            },
+            Node::EndInstance | Node::End => {},
+            Node::Unknown{ data, .. } => out.extend_from_slice(data),
+        }
+
+        let len = out.len() - start;
+        assert!(len <= 0xffff, "device path node too long");
+        assert!(len >= HEADER_SIZE);
+        out[start + 2] = len as u8;
+        out[start + 3] = (len >> 8) as u8;
+    }
+}
+
+impl<'a> PathName<'a> {
+    /// Wraps the UCS-2 encoding of a path, in little-endian byte order.
+    ///
+    /// A NUL terminator is expected, but not required.
+    pub fn from_bytes(bytes: &'a [u8]) -> PathName<'a> {
+        PathName{ bytes: bytes }
+    }
+
+    /// The path as stored in the node, including the NUL terminator.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// Code units of the path, up to the NUL terminator.
+    pub fn units(&self) -> PathUnits<'a> {
+        PathUnits{ bytes: self.bytes }
+    }
+
+    /// Decodes the path. Unpaired surrogates are decoded as U+FFFD.
+    pub fn to_string(&self) -> String {
+        ucs2::decode_units(self.units()).collect()
+    }
+}
+
+impl<'a> ::core::fmt::Debug for PathName<'a> {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+        write!(f, "{:?}", self.to_string())
+    }
+}
+
+/// Iterator over the code units of a `PathName`.
+pub struct PathUnits<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Iterator for PathUnits<'a> {
+    type Item = u16;
+
+    fn next(&mut self) -> Option<u16> {
+        if self.bytes.len() < 2 {
+            return None;
+        }
+        let unit = read_u16(self.bytes, 0);
+        if unit == 0 {
+            self.bytes = &[];
+            return None;
+        }
+        self.bytes = &self.bytes[2..];
+        Some(unit)
+    }
+}
@@ -0,0 +1,1031 @@
+use core::char;
+use core::fmt;
+use core::str::FromStr;
+use core::u64;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use protocol::Guid;
+use ucs2;
+use super::{ACPI_TYPE, BBS_TYPE, DevicePath, DevicePathBuf, DevicePathBuilder, HARDWARE_TYPE, MEDIA_TYPE,
+            MESSAGING_TYPE, Node, PartitionFormat, PartitionSignature, PathName};
+
+/// Error returned when a string is not a device path in text form.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ParseDevicePathError;
+
+type ParseResult<T> = Result<T, ParseDevicePathError>;
+
+/// `HID` of ACPI nodes with a compressed EISA id starting with "PNP".
+const PNP_EISA_ID: u32 = 0x41d0;
+
+const VENDOR_PC_ANSI: Guid = Guid(0xe0c14753,0xf9be,0x11d2,[0x9a,0x0c,0x00,0x90,0x27,0x3f,0xc1,0x4d]);
+const VENDOR_VT100: Guid = Guid(0xdfa66065,0xb419,0x11d3,[0x9a,0x2d,0x00,0x90,0x27,0x3f,0xc1,0x4d]);
+const VENDOR_VT100_PLUS: Guid = Guid(0x7baec70b,0x57e0,0x4c76,[0x8e,0x87,0x2f,0x9e,0x28,0x08,0x83,0x43]);
+const VENDOR_UTF8: Guid = Guid(0xad15a0d6,0x8bec,0x4acf,[0xa0,0x73,0xd0,0x1d,0xe7,0x7e,0x2d,0x88]);
+
+/// Terminal types, shown as `VenPcAnsi()` and the like instead of `VenMsg(...)`.
+const TERMINALS: [(&str, Guid); 4] = [
+    ("VenPcAnsi", VENDOR_PC_ANSI),
+    ("VenVt100", VENDOR_VT100),
+    ("VenVt100Plus", VENDOR_VT100_PLUS),
+    ("VenUtf8", VENDOR_UTF8),
+];
+
+/// USB classes with a dedicated name. The class is implied by the name.
+const USB_CLASSES: [(&str, u8); 12] = [
+    ("UsbAudio", 0x01),
+    ("UsbCDCControl", 0x02),
+    ("UsbHID", 0x03),
+    ("UsbImage", 0x06),
+    ("UsbPrinter", 0x07),
+    ("UsbMassStorage", 0x08),
+    ("UsbHub", 0x09),
+    ("UsbCDCData", 0x0a),
+    ("UsbSmartCard", 0x0b),
+    ("UsbVideo", 0x0e),
+    ("UsbDiagnostic", 0xdc),
+    ("UsbWireless", 0xe0),
+];
+
+/// Sub-classes of the application specific class (0xfe) with a dedicated name.
+const USB_APPLICATION_SUBCLASSES: [(&str, u8); 3] = [
+    ("UsbDeviceFirmwareUpdate", 0x01),
+    ("UsbIrdaBridge", 0x02),
+    ("UsbTestAndMeasurement", 0x03),
+];
+
+/// Well-known PNP ids of ACPI nodes with a dedicated name.
+const ACPI_NAMES: [(&str, u32); 6] = [
+    ("PciRoot", 0x0a03),
+    ("PcieRoot", 0x0a08),
+    ("Floppy", 0x0604),
+    ("Keyboard", 0x0301),
+    ("Serial", 0x0501),
+    ("ParallelPort", 0x0401),
+];
+
+struct EisaId(u32);
+
+impl fmt::Display for EisaId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let letter = |shift: u32| (b'@' + ((self.0 >> shift) & 0x1f) as u8) as char;
+        write!(f, "{}{}{}{:04X}", letter(10), letter(5), letter(0), self.0 >> 16)
+    }
+}
+
+struct Ipv4Address<'a>(&'a [u8; 4]);
+
+impl<'a> fmt::Display for Ipv4Address<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let a = self.0;
+        write!(f, "{}.{}.{}.{}", a[0], a[1], a[2], a[3])
+    }
+}
+
+/// An IPv4 address, followed by `:port` unless the port is zero.
+struct Ipv4Endpoint<'a>(&'a [u8; 4], u16);
+
+impl<'a> fmt::Display for Ipv4Endpoint<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.1 {
+            0 => write!(f, "{}", Ipv4Address(self.0)),
+            port => write!(f, "{}:{}", Ipv4Address(self.0), port),
+        }
+    }
+}
+
+struct Ipv6Address<'a>(&'a [u8; 16]);
+
+impl<'a> fmt::Display for Ipv6Address<'a> {
+    /// Writes the address with the longest run of zero groups compressed, as in RFC 5952.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut groups = [0u16; 8];
+        for (i, group) in groups.iter_mut().enumerate() {
+            *group = (self.0[i*2] as u16) << 8 | self.0[i*2 + 1] as u16;
+        }
+
+        let (mut best_start, mut best_len) = (8, 0);
+        let mut i = 0;
+        while i < 8 {
+            let start = i;
+            while i < 8 && groups[i] == 0 {
+                i += 1;
+            }
+            if i - start > best_len && i - start >= 2 {
+                best_start = start;
+                best_len = i - start;
+            }
+            i += 1;
+        }
+
+        let mut i = 0;
+        while i < 8 {
+            if i == best_start {
+                f.write_str("::")?;
+                i += best_len;
+                continue;
+            }
+            if i != 0 && i != best_start + best_len {
+                f.write_str(":")?;
+            }
+            write!(f, "{:x}", groups[i])?;
+            i += 1;
+        }
+        Ok(())
+    }
+}
+
+/// An IPv6 address, written as `[address]:port` unless the port is zero.
+struct Ipv6Endpoint<'a>(&'a [u8; 16], u16);
+
+impl<'a> fmt::Display for Ipv6Endpoint<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.1 {
+            0 => write!(f, "{}", Ipv6Address(self.0)),
+            port => write!(f, "[{}]:{}", Ipv6Address(self.0), port),
+        }
+    }
+}
+
+/// The partition format `HD()` implies when it isn't written out.
+fn implied_format(signature: PartitionSignature) -> PartitionFormat {
+    match signature {
+        PartitionSignature::Guid(_) => PartitionFormat::Gpt,
+        _ => PartitionFormat::Mbr,
+    }
+}
+
+struct NetworkProtocol(u16);
+
+impl fmt::Display for NetworkProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            6 => f.write_str("TCP"),
+            17 => f.write_str("UDP"),
+            other => write!(f, "0x{:x}", other),
+        }
+    }
+}
+
+fn write_hex(f: &mut fmt::Formatter, data: &[u8]) -> fmt::Result {
+    for b in data {
+        write!(f, "{:02x}", b)?;
+    }
+    Ok(())
+}
+
+fn write_vendor(f: &mut fmt::Formatter, name: &str, guid: Guid, data: &[u8]) -> fmt::Result {
+    write!(f, "{}({}", name, guid)?;
+    if !data.is_empty() {
+        f.write_str(",")?;
+        write_hex(f, data)?;
+    }
+    f.write_str(")")
+}
+
+fn write_usb_class(f: &mut fmt::Formatter, vendor_id: u16, product_id: u16, class: u8, subclass: u8,
+                   protocol: u8) -> fmt::Result {
+    if let Some(&(name, _)) = USB_CLASSES.iter().find(|&&(_, c)| c == class) {
+        return write!(f, "{}(0x{:x},0x{:x},0x{:x},0x{:x})", name, vendor_id, product_id, subclass, protocol);
+    }
+    if class == 0xfe {
+        if let Some(&(name, _)) = USB_APPLICATION_SUBCLASSES.iter().find(|&&(_, s)| s == subclass) {
+            return write!(f, "{}(0x{:x},0x{:x},0x{:x})", name, vendor_id, product_id, protocol);
+        }
+    }
+    write!(f, "UsbClass(0x{:x},0x{:x},0x{:x},0x{:x},0x{:x})", vendor_id, product_id, class, subclass, protocol)
+}
+
+/// Character that makes the next one part of a file path, rather than syntax.
+const ESCAPE: char = '^';
+
+/// Writes a file path, escaping whatever `from_str()` would otherwise take as
+/// separators or node syntax, or trim away.
+fn write_file_path(f: &mut fmt::Formatter, path: PathName) -> fmt::Result {
+    let chars: Vec<char> = ucs2::decode_units(path.units()).collect();
+    let first = chars.iter().position(|c| !c.is_whitespace()).unwrap_or(chars.len());
+    let last = chars.iter().rposition(|c| !c.is_whitespace()).map_or(0, |i| i + 1);
+
+    for (i, &c) in chars.iter().enumerate() {
+        let special = match c {
+            ESCAPE | '/' | ',' | '(' | ')' => true,
+            _ => c.is_whitespace() && (i < first || i >= last),
+        };
+        if special {
+            write!(f, "{}", ESCAPE)?;
+        }
+        write!(f, "{}", c)?;
+    }
+    Ok(())
+}
+
+fn write_generic(f: &mut fmt::Formatter, node_type: u8, sub_type: u8, data: &[u8]) -> fmt::Result {
+    match node_type {
+        HARDWARE_TYPE => write!(f, "HardwarePath({}", sub_type),
+        ACPI_TYPE => write!(f, "AcpiPath({}", sub_type),
+        MESSAGING_TYPE => write!(f, "Msg({}", sub_type),
+        MEDIA_TYPE => write!(f, "MediaPath({}", sub_type),
+        BBS_TYPE => write!(f, "BbsPath({}", sub_type),
+        _ => write!(f, "Path({},{}", node_type, sub_type),
+    }?;
+    if !data.is_empty() {
+        f.write_str(",")?;
+        write_hex(f, data)?;
+    }
+    f.write_str(")")
+}
+
+impl<'a> fmt::Display for Node<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Node::Pci{ function, device } => write!(f, "Pci(0x{:x},0x{:x})", device, function),
+            Node::PcCard{ function } => write!(f, "PcCard(0x{:x})", function),
+            Node::MemoryMapped{ memory_type, start, end } =>
+                write!(f, "MemoryMapped(0x{:x},0x{:x},0x{:x})", memory_type, start, end),
+            Node::VendorHardware{ guid, data } => write_vendor(f, "VenHw", guid, data),
+            Node::Controller{ number } => write!(f, "Ctrl(0x{:x})", number),
+            Node::Bmc{ interface_type, base_address } => write!(f, "BMC({},0x{:x})", interface_type, base_address),
+
+            Node::Acpi{ hid, uid } => {
+                if hid & 0xffff != PNP_EISA_ID {
+                    return write!(f, "Acpi(0x{:08x},0x{:x})", hid, uid);
+                }
+                match ACPI_NAMES.iter().find(|&&(_, id)| id == hid >> 16) {
+                    Some(&(name, _)) => write!(f, "{}(0x{:x})", name, uid),
+                    None => write!(f, "Acpi(PNP{:04x},0x{:x})", hid >> 16, uid),
+                }
+            },
+            Node::ExpandedAcpi{ hid, uid, cid, hid_str, uid_str, cid_str } => {
+                if hid_str.is_empty() && cid_str.is_empty() && !uid_str.is_empty() {
+                    if cid == 0 {
+                        write!(f, "AcpiExp({},0,{})", EisaId(hid), uid_str)
+                    } else {
+                        write!(f, "AcpiExp({},{},{})", EisaId(hid), EisaId(cid), uid_str)
+                    }
+                } else {
+                    write!(f, "AcpiEx({},{},0x{:x},{},{},{})", EisaId(hid), EisaId(cid), uid, hid_str, cid_str, uid_str)
+                }
+            },
+            Node::Adr{ adr } => write!(f, "AcpiAdr(0x{:x})", adr),
+
+            Node::Atapi{ secondary, slave, lun } => write!(f, "Ata({},{},0x{:x})",
+                                                           if secondary { "Secondary" } else { "Primary" },
+                                                           if slave { "Slave" } else { "Master" }, lun),
+            Node::Scsi{ target, lun } => write!(f, "Scsi(0x{:x},0x{:x})", target, lun),
+            Node::Usb{ parent_port, interface } => write!(f, "USB(0x{:x},0x{:x})", parent_port, interface),
+            Node::VendorMessaging{ guid, data } => {
+                match TERMINALS.iter().find(|&&(_, g)| g == guid) {
+                    Some(&(name, _)) if data.is_empty() => write!(f, "{}()", name),
+                    _ => write_vendor(f, "VenMsg", guid, data),
+                }
+            },
+            Node::MacAddress{ ref address, if_type } => {
+                // Ethernet and IEEE 802.3 addresses are 6 bytes, the rest is padding.
+                let len = if if_type <= 1 { 6 } else { 32 };
+                f.write_str("MAC(")?;
+                write_hex(f, &address[..len])?;
+                write!(f, ",0x{:x})", if_type)
+            },
+            Node::Ipv4{ ref local, ref remote, local_port, remote_port, protocol, static_address,
+                        gateway, subnet_mask } => {
+                write!(f, "IPv4({},{},{},{}", Ipv4Endpoint(remote, remote_port), NetworkProtocol(protocol),
+                       if static_address { "Static" } else { "DHCP" }, Ipv4Endpoint(local, local_port))?;
+                // Left out for nodes from before UEFI 2.3, as EDK2 does.
+                if gateway.is_some() || subnet_mask.is_some() {
+                    write!(f, ",{},{}", Ipv4Address(&gateway.unwrap_or([0; 4])),
+                           Ipv4Address(&subnet_mask.unwrap_or([0; 4])))?;
+                }
+                f.write_str(")")
+            },
+            Node::Ipv6{ ref local, ref remote, local_port, remote_port, protocol, origin, prefix_length,
+                        gateway } => {
+                let origin = match origin {
+                    0 => "Static",
+                    1 => "StatelessAutoConfigure",
+                    _ => "StatefulAutoConfigure",
+                };
+                write!(f, "IPv6({},{},{},{}", Ipv6Endpoint(remote, remote_port), NetworkProtocol(protocol), origin,
+                       Ipv6Endpoint(local, local_port))?;
+                if gateway.is_some() || prefix_length.is_some() {
+                    write!(f, ",{},{}", Ipv6Address(&gateway.unwrap_or([0; 16])), prefix_length.unwrap_or(0))?;
+                }
+                f.write_str(")")
+            },
+            Node::UsbClass{ vendor_id, product_id, class, subclass, protocol } =>
+                write_usb_class(f, vendor_id, product_id, class, subclass, protocol),
+            Node::Sata{ hba_port, port_multiplier_port, lun } =>
+                write!(f, "Sata(0x{:x},0x{:x},0x{:x})", hba_port, port_multiplier_port, lun),
+            Node::Nvme{ namespace_id, eui64: e } =>
+                write!(f, "NVMe(0x{:x},{:02x}-{:02x}-{:02x}-{:02x}-{:02x}-{:02x}-{:02x}-{:02x})",
+                       namespace_id, e[7], e[6], e[5], e[4], e[3], e[2], e[1], e[0]),
+            Node::Uri{ uri } => {
+                f.write_str("Uri(")?;
+                for &b in uri {
+                    write!(f, "{}", if b.is_ascii() { b as char } else { char::REPLACEMENT_CHARACTER })?;
+                }
+                f.write_str(")")
+            },
+
+            Node::HardDrive{ partition_number, partition_start, partition_size, format, signature } => {
+                match signature {
+                    PartitionSignature::Mbr(sig) => write!(f, "HD({},MBR,0x{:08x},", partition_number, sig),
+                    PartitionSignature::Guid(guid) => write!(f, "HD({},GPT,{},", partition_number, guid),
+                    PartitionSignature::None => write!(f, "HD({},0,0,", partition_number),
+                    PartitionSignature::Other{ signature_type, ref signature } => {
+                        write!(f, "HD({},{},", partition_number, signature_type)?;
+                        write_hex(f, signature)?;
+                        f.write_str(",")
+                    },
+                }?;
+                write!(f, "0x{:x},0x{:x}", partition_start, partition_size)?;
+                // EDK2 has no room for the format, so it's only written when unusual.
+                if format != implied_format(signature) {
+                    write!(f, ",0x{:x}", u8::from(format))?;
+                }
+                f.write_str(")")
+            },
+            Node::CdRom{ boot_entry, partition_start, partition_size } =>
+                write!(f, "CDROM(0x{:x},0x{:x},0x{:x})", boot_entry, partition_start, partition_size),
+            Node::VendorMedia{ guid, data } => write_vendor(f, "VenMedia", guid, data),
+            Node::FilePath{ path } => write_file_path(f, path),
+            Node::MediaProtocol{ protocol } => write!(f, "Media({})", protocol),
+            Node::FirmwareFile{ name } => write!(f, "FvFile({})", name),
+            Node::FirmwareVolume{ name } => write!(f, "Fv({})", name),
+            Node::RelativeOffsetRange{ start, end } => write!(f, "Offset(0x{:x},0x{:x})", start, end),
+
+            Node::EndInstance => f.write_str(","),
+            Node::End => Ok(()),
+
+            Node::Unknown{ node_type, sub_type, data } => write_generic(f, node_type, sub_type, data),
+        }
+    }
+}
+
+/// The text form of device paths, modelled on the output of the EDK2
+/// `DevicePathToText` protocol, and read back by `from_str()`.
+///
+/// Nodes are separated by `/`, and instances by `,`. Well-known nodes are
+/// written with their dedicated names, such as `PciRoot()`, `VenPcAnsi()` or
+/// `UsbHID()`, and numbers in lowercase hex, except for the product part of
+/// EISA ids like `PNP0A03`. Unlike in EDK2's output, non-zero IP ports are
+/// written as `address:port`, a partition format that `HD()` doesn't imply
+/// is added as a last argument, and characters of file paths that would
+/// otherwise be taken as syntax or trimmed are escaped with `^`.
+impl fmt::Display for DevicePath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut separate = false;
+        for node in self.nodes() {
+            if node == Node::EndInstance {
+                f.write_str(",")?;
+                separate = false;
+                continue;
+            }
+            if separate {
+                f.write_str("/")?;
+            }
+            write!(f, "{}", node)?;
+            separate = true;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for DevicePathBuf {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self.as_device_path(), f)
+    }
+}
+
+/// Arguments of a node, missing ones being empty as in EDK2.
+struct Args<'a> {
+    rest: Option<&'a str>,
+}
+
+impl<'a> Args<'a> {
+    fn new(s: &'a str) -> Args<'a> {
+        Args{ rest: if s.is_empty() { None } else { Some(s) } }
+    }
+
+    fn next(&mut self) -> &'a str {
+        match self.rest {
+            Some(s) => match s.find(',') {
+                Some(pos) => {
+                    self.rest = Some(&s[pos+1..]);
+                    s[..pos].trim()
+                },
+                None => {
+                    self.rest = None;
+                    s.trim()
+                },
+            },
+            None => "",
+        }
+    }
+
+    /// Whether there are arguments left, even empty ones.
+    fn has_more(&self) -> bool {
+        self.rest.is_some()
+    }
+
+    fn int(&mut self, max: u64) -> ParseResult<u64> {
+        parse_int(self.next(), max)
+    }
+
+    fn guid(&mut self) -> ParseResult<Guid> {
+        Guid::from_str(self.next()).map_err(|_| ParseDevicePathError)
+    }
+
+    fn hex(&mut self) -> ParseResult<Vec<u8>> {
+        parse_hex(self.next())
+    }
+}
+
+/// Parses a number in decimal, or in hex with a `0x` prefix. Empty means zero.
+fn parse_int(s: &str, max: u64) -> ParseResult<u64> {
+    if s.is_empty() {
+        return Ok(0);
+    }
+    let value = if s.starts_with("0x") || s.starts_with("0X") {
+        u64::from_str_radix(&s[2..], 16)
+    } else {
+        u64::from_str_radix(s, 10)
+    };
+    match value {
+        Ok(v) if v <= max => Ok(v),
+        _ => Err(ParseDevicePathError),
+    }
+}
+
+fn parse_hex(s: &str) -> ParseResult<Vec<u8>> {
+    if s.len() % 2 != 0 {
+        return Err(ParseDevicePathError);
+    }
+    let mut data = Vec::with_capacity(s.len() / 2);
+    for i in 0..s.len() / 2 {
+        let byte = s.get(i*2..i*2 + 2).ok_or(ParseDevicePathError)?;
+        data.push(u8::from_str_radix(byte, 16).map_err(|_| ParseDevicePathError)?);
+    }
+    Ok(data)
+}
+
+/// Parses an EISA id such as `PNP0A03`, or a plain number.
+fn parse_eisa_id(s: &str) -> ParseResult<u32> {
+    let b = s.as_bytes();
+    if b.len() == 7 && b[..3].iter().all(|c| c.is_ascii_uppercase() || *c == b'@') {
+        let product = u32::from_str_radix(&s[3..], 16).map_err(|_| ParseDevicePathError)?;
+        let letter = |c: u8| ((c - b'@') & 0x1f) as u32;
+        return Ok(letter(b[0]) << 10 | letter(b[1]) << 5 | letter(b[2]) | product << 16);
+    }
+    parse_int(s, 0xffff_ffff).map(|v| v as u32)
+}
+
+fn parse_protocol(s: &str) -> ParseResult<u16> {
+    match s {
+        "TCP" => Ok(6),
+        "UDP" => Ok(17),
+        _ => parse_int(s, 0xffff).map(|v| v as u16),
+    }
+}
+
+fn parse_ipv4(s: &str) -> ParseResult<[u8; 4]> {
+    let mut address = [0; 4];
+    if s.is_empty() {
+        return Ok(address);
+    }
+    let mut parts = s.split('.');
+    for byte in address.iter_mut() {
+        let part = parts.next().ok_or(ParseDevicePathError)?;
+        *byte = part.parse().map_err(|_| ParseDevicePathError)?;
+    }
+    if parts.next().is_some() {
+        return Err(ParseDevicePathError);
+    }
+    Ok(address)
+}
+
+/// Parses an address written by `Ipv4Endpoint`.
+fn parse_ipv4_endpoint(s: &str) -> ParseResult<([u8; 4], u16)> {
+    match s.find(':') {
+        Some(pos) => Ok((parse_ipv4(&s[..pos])?, parse_int(&s[pos+1..], 0xffff)? as u16)),
+        None => Ok((parse_ipv4(s)?, 0)),
+    }
+}
+
+/// Parses an address written by `Ipv6Endpoint`.
+fn parse_ipv6_endpoint(s: &str) -> ParseResult<([u8; 16], u16)> {
+    if !s.starts_with('[') {
+        return Ok((parse_ipv6(s)?, 0));
+    }
+    let end = s.find("]:").ok_or(ParseDevicePathError)?;
+    Ok((parse_ipv6(&s[1..end])?, parse_int(&s[end+2..], 0xffff)? as u16))
+}
+
+fn parse_ipv6(s: &str) -> ParseResult<[u8; 16]> {
+    let mut address = [0; 16];
+    if s.is_empty() {
+        return Ok(address);
+    }
+
+    fn groups(s: &str, out: &mut Vec<u16>) -> ParseResult<()> {
+        if s.is_empty() {
+            return Ok(());
+        }
+        for part in s.split(':') {
+            if part.is_empty() || part.len() > 4 {
+                return Err(ParseDevicePathError);
+            }
+            out.push(u16::from_str_radix(part, 16).map_err(|_| ParseDevicePathError)?);
+        }
+        Ok(())
+    }
+
+    let mut head = Vec::new();
+    let mut tail = Vec::new();
+    match s.find("::") {
+        Some(pos) => {
+            groups(&s[..pos], &mut head)?;
+            groups(&s[pos+2..], &mut tail)?;
+            if head.len() + tail.len() > 7 {
+                return Err(ParseDevicePathError);
+            }
+        },
+        None => {
+            groups(s, &mut head)?;
+            if head.len() != 8 {
+                return Err(ParseDevicePathError);
+            }
+        },
+    }
+
+    let tail_start = 8 - tail.len();
+    let positioned = head.iter().enumerate().chain(tail.iter().enumerate().map(|(i, g)| (tail_start + i, g)));
+    for (i, &group) in positioned {
+        address[i*2] = (group >> 8) as u8;
+        address[i*2 + 1] = group as u8;
+    }
+    Ok(address)
+}
+
+/// Parses a node written as `Name(args)`, and appends it to `builder`.
+///
+/// Returns `false` if `name` is not a known node, in which case the text
+/// is taken as a file path, as EDK2 does.
+fn parse_node(builder: &mut DevicePathBuilder, name: &str, args: &str) -> ParseResult<bool> {
+    const U8: u64 = 0xff;
+    const U16: u64 = 0xffff;
+    const U32: u64 = 0xffff_ffff;
+
+    let mut a = Args::new(args);
+
+    if let Some(&(_, id)) = ACPI_NAMES.iter().find(|&&(n, _)| n == name) {
+        let uid = a.int(U32)? as u32;
+        builder.push(&Node::Acpi{ hid: id << 16 | PNP_EISA_ID, uid: uid });
+        return Ok(true);
+    }
+    if let Some(&(_, guid)) = TERMINALS.iter().find(|&&(n, _)| n == name) {
+        builder.push(&Node::VendorMessaging{ guid: guid, data: &[] });
+        return Ok(true);
+    }
+    if let Some(&(_, class)) = USB_CLASSES.iter().find(|&&(n, _)| n == name) {
+        let vendor_id = a.int(U16)? as u16;
+        let product_id = a.int(U16)? as u16;
+        let subclass = a.int(U8)? as u8;
+        let protocol = a.int(U8)? as u8;
+        builder.push(&Node::UsbClass{ vendor_id: vendor_id, product_id: product_id, class: class,
+                                      subclass: subclass, protocol: protocol });
+        return Ok(true);
+    }
+    if let Some(&(_, subclass)) = USB_APPLICATION_SUBCLASSES.iter().find(|&&(n, _)| n == name) {
+        let vendor_id = a.int(U16)? as u16;
+        let product_id = a.int(U16)? as u16;
+        let protocol = a.int(U8)? as u8;
+        builder.push(&Node::UsbClass{ vendor_id: vendor_id, product_id: product_id, class: 0xfe,
+                                      subclass: subclass, protocol: protocol });
+        return Ok(true);
+    }
+
+    match name {
+        "Pci" => {
+            let device = a.int(U8)? as u8;
+            let function = a.int(U8)? as u8;
+            builder.push(&Node::Pci{ function: function, device: device });
+        },
+        "PcCard" => {
+            let function = a.int(U8)? as u8;
+            builder.push(&Node::PcCard{ function: function });
+        },
+        "MemoryMapped" => {
+            let memory_type = a.int(U32)? as u32;
+            let start = a.int(u64::MAX)?;
+            let end = a.int(u64::MAX)?;
+            builder.push(&Node::MemoryMapped{ memory_type: memory_type, start: start, end: end });
+        },
+        "VenHw" | "VenMsg" | "VenMedia" => {
+            let guid = a.guid()?;
+            let data = a.hex()?;
+            builder.push(&match name {
+                "VenHw" => Node::VendorHardware{ guid: guid, data: &data },
+                "VenMsg" => Node::VendorMessaging{ guid: guid, data: &data },
+                _ => Node::VendorMedia{ guid: guid, data: &data },
+            });
+        },
+        "Ctrl" => {
+            let number = a.int(U32)? as u32;
+            builder.push(&Node::Controller{ number: number });
+        },
+        "BMC" => {
+            let interface_type = a.int(U8)? as u8;
+            let base_address = a.int(u64::MAX)?;
+            builder.push(&Node::Bmc{ interface_type: interface_type, base_address: base_address });
+        },
+
+        "Acpi" => {
+            let hid = parse_eisa_id(a.next())?;
+            let uid = a.int(U32)? as u32;
+            builder.push(&Node::Acpi{ hid: hid, uid: uid });
+        },
+        "AcpiEx" => {
+            let hid = parse_eisa_id(a.next())?;
+            let cid = parse_eisa_id(a.next())?;
+            let uid = a.int(U32)? as u32;
+            let hid_str = a.next();
+            let cid_str = a.next();
+            let uid_str = a.next();
+            builder.push(&Node::ExpandedAcpi{ hid: hid, uid: uid, cid: cid,
+                                              hid_str: hid_str, uid_str: uid_str, cid_str: cid_str });
+        },
+        "AcpiExp" => {
+            let hid = parse_eisa_id(a.next())?;
+            let cid = parse_eisa_id(a.next())?;
+            let uid_str = a.next();
+            builder.push(&Node::ExpandedAcpi{ hid: hid, uid: 0, cid: cid,
+                                              hid_str: "", uid_str: uid_str, cid_str: "" });
+        },
+        "AcpiAdr" => {
+            let adr = a.int(U32)? as u32;
+            builder.push(&Node::Adr{ adr: adr });
+        },
+
+        "Ata" => {
+            let secondary = match a.next() {
+                "Primary" => false,
+                "Secondary" => true,
+                other => parse_int(other, 1)? != 0,
+            };
+            let slave = match a.next() {
+                "Master" => false,
+                "Slave" => true,
+                other => parse_int(other, 1)? != 0,
+            };
+            let lun = a.int(U16)? as u16;
+            builder.push(&Node::Atapi{ secondary: secondary, slave: slave, lun: lun });
+        },
+        "Scsi" => {
+            let target = a.int(U16)? as u16;
+            let lun = a.int(U16)? as u16;
+            builder.push(&Node::Scsi{ target: target, lun: lun });
+        },
+        "USB" => {
+            let parent_port = a.int(U8)? as u8;
+            let interface = a.int(U8)? as u8;
+            builder.push(&Node::Usb{ parent_port: parent_port, interface: interface });
+        },
+        "MAC" => {
+            let bytes = a.hex()?;
+            if bytes.len() > 32 {
+                return Err(ParseDevicePathError);
+            }
+            let mut address = [0; 32];
+            address[..bytes.len()].copy_from_slice(&bytes);
+            let if_type = a.int(U8)? as u8;
+            builder.push(&Node::MacAddress{ address: address, if_type: if_type });
+        },
+        // Without gateway and subnet mask, the node has the format from before UEFI 2.3.
+        "IPv4" => {
+            let (remote, remote_port) = parse_ipv4_endpoint(a.next())?;
+            let protocol = parse_protocol(a.next())?;
+            let static_address = a.next() == "Static";
+            let (local, local_port) = parse_ipv4_endpoint(a.next())?;
+            let (gateway, subnet_mask) = if a.has_more() {
+                (Some(parse_ipv4(a.next())?), Some(parse_ipv4(a.next())?))
+            } else {
+                (None, None)
+            };
+            builder.push(&Node::Ipv4{ local: local, remote: remote, local_port: local_port, remote_port: remote_port,
+                                      protocol: protocol, static_address: static_address,
+                                      gateway: gateway, subnet_mask: subnet_mask });
+        },
+        "IPv6" => {
+            let (remote, remote_port) = parse_ipv6_endpoint(a.next())?;
+            let protocol = parse_protocol(a.next())?;
+            let origin = match a.next() {
+                "Static" | "" => 0,
+                "StatelessAutoConfigure" => 1,
+                "StatefulAutoConfigure" => 2,
+                _ => return Err(ParseDevicePathError),
+            };
+            let (local, local_port) = parse_ipv6_endpoint(a.next())?;
+            let (gateway, prefix_length) = if a.has_more() {
+                (Some(parse_ipv6(a.next())?), Some(a.int(U8)? as u8))
+            } else {
+                (None, None)
+            };
+            builder.push(&Node::Ipv6{ local: local, remote: remote, local_port: local_port, remote_port: remote_port,
+                                      protocol: protocol, origin: origin, prefix_length: prefix_length,
+                                      gateway: gateway });
+        },
+        "UsbClass" => {
+            let vendor_id = a.int(U16)? as u16;
+            let product_id = a.int(U16)? as u16;
+            let class = a.int(U8)? as u8;
+            let subclass = a.int(U8)? as u8;
+            let protocol = a.int(U8)? as u8;
+            builder.push(&Node::UsbClass{ vendor_id: vendor_id, product_id: product_id, class: class,
+                                          subclass: subclass, protocol: protocol });
+        },
+        "Sata" => {
+            let hba_port = a.int(U16)? as u16;
+            let port_multiplier_port = a.int(U16)? as u16;
+            let lun = a.int(U16)? as u16;
+            builder.push(&Node::Sata{ hba_port: hba_port, port_multiplier_port: port_multiplier_port, lun: lun });
+        },
+        "NVMe" => {
+            let namespace_id = a.int(U32)? as u32;
+            let mut eui64 = [0; 8];
+            let mut parts = a.next().split('-');
+            for byte in eui64.iter_mut().rev() {
+                let part = parts.next().ok_or(ParseDevicePathError)?;
+                *byte = u8::from_str_radix(part, 16).map_err(|_| ParseDevicePathError)?;
+            }
+            builder.push(&Node::Nvme{ namespace_id: namespace_id, eui64: eui64 });
+        },
+        // The URI may contain commas.
+        "Uri" => { builder.push(&Node::Uri{ uri: args.as_bytes() }); },
+
+        "HD" => {
+            let partition_number = a.int(U32)? as u32;
+            let signature = match a.next() {
+                "MBR" => PartitionSignature::Mbr(a.int(U32)? as u32),
+                "GPT" => PartitionSignature::Guid(a.guid()?),
+                other => match parse_int(other, U8)? as u8 {
+                    0 => {
+                        a.next();
+                        PartitionSignature::None
+                    },
+                    signature_type => {
+                        let bytes = a.hex()?;
+                        if bytes.len() > 16 {
+                            return Err(ParseDevicePathError);
+                        }
+                        let mut signature = [0; 16];
+                        signature[..bytes.len()].copy_from_slice(&bytes);
+                        PartitionSignature::Other{ signature_type: signature_type, signature: signature }
+                    },
+                },
+            };
+            let partition_start = a.int(u64::MAX)?;
+            let partition_size = a.int(u64::MAX)?;
+            let format = match a.next() {
+                "" => implied_format(signature),
+                other => PartitionFormat::from(parse_int(other, U8)? as u8),
+            };
+            builder.push(&Node::HardDrive{ partition_number: partition_number, partition_start: partition_start,
+                                           partition_size: partition_size, format: format, signature: signature });
+        },
+        "CDROM" => {
+            let boot_entry = a.int(U32)? as u32;
+            let partition_start = a.int(u64::MAX)?;
+            let partition_size = a.int(u64::MAX)?;
+            builder.push(&Node::CdRom{ boot_entry: boot_entry, partition_start: partition_start,
+                                       partition_size: partition_size });
+        },
+        "Media" => { builder.push(&Node::MediaProtocol{ protocol: a.guid()? }); },
+        "FvFile" => { builder.push(&Node::FirmwareFile{ name: a.guid()? }); },
+        "Fv" => { builder.push(&Node::FirmwareVolume{ name: a.guid()? }); },
+        "Offset" => {
+            let start = a.int(u64::MAX)?;
+            let end = a.int(u64::MAX)?;
+            builder.push(&Node::RelativeOffsetRange{ start: start, end: end });
+        },
+
+        "HardwarePath" | "AcpiPath" | "Msg" | "MediaPath" | "BbsPath" | "Path" => {
+            let node_type = match name {
+                "HardwarePath" => HARDWARE_TYPE,
+                "AcpiPath" => ACPI_TYPE,
+                "Msg" => MESSAGING_TYPE,
+                "MediaPath" => MEDIA_TYPE,
+                "BbsPath" => BBS_TYPE,
+                _ => a.int(U8)? as u8,
+            };
+            let sub_type = a.int(U8)? as u8;
+            let data = a.hex()?;
+            builder.push(&Node::Unknown{ node_type: node_type, sub_type: sub_type, data: &data });
+        },
+
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+/// Splits `s` at the top-level `/` and `,`, i.e. those neither within parentheses
+/// nor escaped. Returns the part before the separator, the separator, and the rest.
+fn split_top_level(s: &str) -> (&str, Option<char>, &str) {
+    let mut depth = 0usize;
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match c {
+            ESCAPE => escaped = true,
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            '/' | ',' if depth == 0 => return (&s[..i], Some(c), &s[i+1..]),
+            _ => {},
+        }
+    }
+    (s, None, "")
+}
+
+/// Like `str::trim()`, but keeps whitespace that is escaped.
+fn trim_unescaped(s: &str) -> &str {
+    let s = s.trim_start();
+    let mut end = 0;
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        if escaped || !c.is_whitespace() {
+            end = i + c.len_utf8();
+        }
+        escaped = !escaped && c == ESCAPE;
+    }
+    &s[..end]
+}
+
+/// Removes the escapes written by `write_file_path()`.
+fn unescape_file_path(s: &str) -> ParseResult<String> {
+    let mut path = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == ESCAPE {
+            path.push(chars.next().ok_or(ParseDevicePathError)?);
+        } else {
+            path.push(c);
+        }
+    }
+    Ok(path)
+}
+
+fn parse_segment(builder: &mut DevicePathBuilder, segment: &str) -> ParseResult<()> {
+    if segment.ends_with(')') {
+        if let Some(open) = segment.find('(') {
+            let name = &segment[..open];
+            if !name.is_empty() && name.bytes().all(|c| c.is_ascii_alphanumeric()) {
+                if parse_node(builder, name, &segment[open+1..segment.len()-1])? {
+                    return Ok(());
+                }
+            }
+        }
+    }
+    builder.push_file_path(&unescape_file_path(segment)?);
+    Ok(())
+}
+
+impl FromStr for DevicePathBuf {
+    type Err = ParseDevicePathError;
+
+    /// Parses the text form of a device path, as written by `Display`.
+    ///
+    /// Text that isn't a known node is taken as a `FilePath`,
+    /// so a single-node path can be written as `\EFI\BOOT\BOOTX64.EFI`.
+    /// Within it, `^` makes the next character part of the path.
+    fn from_str(s: &str) -> Result<DevicePathBuf, ParseDevicePathError> {
+        let mut builder = DevicePathBuilder::new();
+        let mut rest = trim_unescaped(s);
+        while !rest.is_empty() {
+            let (segment, separator, tail) = split_top_level(rest);
+            let segment = trim_unescaped(segment);
+            if !segment.is_empty() {
+                parse_segment(&mut builder, segment)?;
+            }
+            if separator == Some(',') {
+                builder.end_instance();
+            }
+            rest = tail;
+        }
+        Ok(builder.finish())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::{String, ToString};
+    use core::str::FromStr;
+    use super::super::tests::{legacy_ipv4, legacy_ipv6, ovmf_pxe, ovmf_sata};
+    use super::super::{DevicePath, DevicePathBuf, DevicePathBuilder, Node, PartitionFormat, PartitionSignature};
+
+    /// Checks that `bytes` is written as `text`, which parses back to the same bytes.
+    fn assert_round_trip(bytes: &[u8], text: &str) {
+        let path = DevicePath::from_bytes(bytes).unwrap();
+        assert_eq!(path.to_string(), text);
+        let parsed = DevicePathBuf::from_str(text).unwrap();
+        assert_eq!(parsed.as_bytes(), bytes);
+    }
+
+    #[test]
+    fn sata_path() {
+        assert_round_trip(&ovmf_sata(), "PciRoot(0x0)/Pci(0x1f,0x2)/Sata(0x0,0xffff,0x0)/\
+                                         HD(1,GPT,3e0f9df5-de5b-4c84-9e0f-5a9a2b1e8d7c,0x800,0x32000)/\
+                                         \\EFI\\BOOT\\BOOTX64.EFI");
+    }
+
+    #[test]
+    fn pxe_path() {
+        assert_round_trip(&ovmf_pxe(), "PciRoot(0x0)/Pci(0x3,0x0)/MAC(525400123456,0x1)/\
+                                        IPv4(0.0.0.0,0x0,DHCP,0.0.0.0,0.0.0.0,0.0.0.0)");
+    }
+
+    #[test]
+    fn legacy_ip_nodes_with_ports() {
+        assert_round_trip(&legacy_ipv4(), "IPv4(192.168.0.1:67,UDP,DHCP,192.168.0.2:68)");
+        assert_round_trip(&legacy_ipv6(), "IPv6([fe80::1]:547,UDP,StatelessAutoConfigure,\
+                                           [fe80::5054:ff:fe12:3456]:546)");
+    }
+
+    #[test]
+    fn hard_drive_other_signatures() {
+        let mut signature = [0; 16];
+        for (i, b) in signature.iter_mut().enumerate() {
+            *b = i as u8;
+        }
+        let nodes = [
+            Node::HardDrive{ partition_number: 2, partition_start: 0x800, partition_size: 0x1000,
+                             format: PartitionFormat::Gpt,
+                             signature: PartitionSignature::Other{ signature_type: 3, signature: signature } },
+            Node::HardDrive{ partition_number: 1, partition_start: 0x3f, partition_size: 0x100,
+                             format: PartitionFormat::Mbr, signature: PartitionSignature::None },
+            Node::HardDrive{ partition_number: 1, partition_start: 0x22, partition_size: 0x100,
+                             format: PartitionFormat::Other(7), signature: PartitionSignature::Mbr(0x12345678) },
+        ];
+        let texts = [
+            "HD(2,3,000102030405060708090a0b0c0d0e0f,0x800,0x1000,0x2)",
+            "HD(1,0,0,0x3f,0x100)",
+            "HD(1,MBR,0x12345678,0x22,0x100,0x7)",
+        ];
+        for (node, &text) in nodes.iter().zip(texts.iter()) {
+            let bytes = DevicePathBuilder::new().push(node).finish().into_bytes();
+            assert_round_trip(&bytes, text);
+        }
+    }
+
+    #[test]
+    fn eisa_ids() {
+        let nodes = [
+            Node::ExpandedAcpi{ hid: 0x0a08 << 16 | 0x41d0, uid: 0, cid: 0x0a03 << 16 | 0x41d0,
+                                hid_str: "", uid_str: "", cid_str: "" },
+            Node::ExpandedAcpi{ hid: 0x0a0e << 16 | 0x41d0, uid: 0, cid: 0, hid_str: "", uid_str: "ab", cid_str: "" },
+        ];
+        let texts = [
+            "AcpiEx(PNP0A08,PNP0A03,0x0,,,)",
+            "AcpiExp(PNP0A0E,0,ab)",
+        ];
+        for (node, &text) in nodes.iter().zip(texts.iter()) {
+            let bytes = DevicePathBuilder::new().push(node).finish().into_bytes();
+            assert_round_trip(&bytes, text);
+        }
+    }
+
+    #[test]
+    fn file_path_escapes() {
+        let paths = [
+            ("\\a,b/c", "\\a^,b^/c"),
+            ("\\Pci(0x1,0x0)", "\\Pci^(0x1^,0x0^)"),
+            ("Pci(0x1)", "Pci^(0x1^)"),
+            ("  \\a b  ", "^ ^ \\a b^ ^ "),
+            ("\\x^y^", "\\x^^y^^"),
+            ("\t", "^\t"),
+        ];
+        for &(path, text) in &paths {
+            let bytes = DevicePathBuilder::new().push_file_path(path).finish().into_bytes();
+            assert_round_trip(&bytes, text);
+        }
+
+        // Escapes work in any instance, and a trailing escape has nothing to escape.
+        let bytes = DevicePathBuilder::new().push_file_path(" a").end_instance().push_file_path("b,").finish()
+            .into_bytes();
+        assert_round_trip(&bytes, "^ a,b^,");
+        assert!(DevicePathBuf::from_str("\\a^").is_err());
+    }
+
+    #[test]
+    fn file_path_with_unpaired_surrogate() {
+        let mut bytes = DevicePathBuilder::new().push_file_path("\\a").finish().into_bytes();
+        // Replace "a" with a lone high surrogate.
+        bytes[6] = 0x00;
+        bytes[7] = 0xd8;
+        let text: String = DevicePath::from_bytes(&bytes).unwrap().to_string();
+        assert_eq!(text, "\\\u{fffd}");
+    }
+}
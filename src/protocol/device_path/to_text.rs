@@ -0,0 +1,64 @@
+use efi_types;
+
+use protocol::{Error, Guid, Result};
+use protocol::pool::PoolSlice;
+use string::{CStr16, CString16};
+use ucs2;
+use super::{DevicePath, RawNode};
+
+/// The firmware's conversion of device paths to text, which is optional.
+///
+/// `DevicePath` implements `Display` without it. This is mostly useful
+/// to check that both agree.
+pub struct DevicePathToText {
+    interface: efi_types::EFI_DEVICE_PATH_TO_TEXT_PROTOCOL,
+}
+
+unsafe impl ::protocol::Protocol for DevicePathToText {
+    const GUID: Guid = Guid(0x8b843e20,0x8132,0x4852,[0x90,0xcc,0x55,0x1a,0x4e,0x4a,0x7f,0x1c]);
+}
+
+/// Takes ownership of a string returned by the protocol.
+unsafe fn take_string(p: *mut u16) -> Result<CString16> {
+    if p.is_null() {
+        return Err(Error::OUT_OF_RESOURCES);
+    }
+    let buffer = PoolSlice::from_raw(p, ucs2::strlen(p) + 1);
+    Ok(CString16::from(CStr16::from_u16_with_nul_unchecked(&buffer)))
+}
+
+impl DevicePathToText {
+    /// Converts a single node.
+    ///
+    /// `display_only` selects the shorter form meant for display, which can't
+    /// be converted back. `allow_shortcuts` allows names such as `VenPcAnsi()`
+    /// that don't apply to all nodes of the type.
+    ///
+    /// **Errors**
+    ///
+    /// * `EFI_OUT_OF_RESOURCES`
+    ///     * The firmware couldn't allocate the string.
+    ///
+    pub fn node_to_text(&self, node: RawNode, display_only: bool, allow_shortcuts: bool) -> Result<CString16> {
+        let func = self.interface.ConvertDeviceNodeToText.unwrap();
+        unsafe {
+            let p = func(node.as_bytes().as_ptr() as *mut _, display_only as _, allow_shortcuts as _);
+            take_string(p as *mut u16)
+        }
+    }
+
+    /// Converts a device path.
+    ///
+    /// **Errors**
+    ///
+    /// * `EFI_OUT_OF_RESOURCES`
+    ///     * The firmware couldn't allocate the string.
+    ///
+    pub fn path_to_text(&self, path: &DevicePath, display_only: bool, allow_shortcuts: bool) -> Result<CString16> {
+        let func = self.interface.ConvertDevicePathToText.unwrap();
+        unsafe {
+            let p = func(path.as_ptr() as *mut _, display_only as _, allow_shortcuts as _);
+            take_string(p as *mut u16)
+        }
+    }
+}
@@ -0,0 +1,134 @@
+extern crate alloc;
+use self::alloc::boxed::Box;
+use efi_types;
+use core::ops::BitOr;
+use core::ptr;
+use core::time::Duration;
+
+use globals;
+
+/// Type of an event, passed to `BootServices::create_event()`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct EventType(u32);
+
+impl EventType {
+    pub const TIMER: EventType = EventType(0x80000000);
+    pub const RUNTIME: EventType = EventType(0x40000000);
+    pub const NOTIFY_WAIT: EventType = EventType(0x00000100);
+    pub const NOTIFY_SIGNAL: EventType = EventType(0x00000200);
+    pub const SIGNAL_EXIT_BOOT_SERVICES: EventType = EventType(0x00000201);
+    pub const SIGNAL_VIRTUAL_ADDRESS_CHANGE: EventType = EventType(0x60000202);
+
+    #[inline]
+    pub const fn empty() -> Self {
+        EventType(0)
+    }
+
+    #[inline]
+    pub fn bits(self) -> u32 {
+        self.0
+    }
+}
+
+impl BitOr for EventType {
+    type Output = EventType;
+
+    #[inline]
+    fn bitor(self, other: EventType) -> EventType {
+        EventType(self.0 | other.0)
+    }
+}
+
+/// Timer setting, passed to `BootServices::set_timer()`.
+///
+/// The firmware timer resolution is 100 ns.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Timer {
+    /// Cancels the timer.
+    Cancel,
+    /// Signals the event every time the given period elapses.
+    Periodic(Duration),
+    /// Signals the event once, after the given time elapses.
+    Relative(Duration),
+}
+
+impl Timer {
+    /// Returns `(EFI_TIMER_DELAY, TriggerTime)`.
+    pub(crate) fn to_raw(self) -> (u32, u64) {
+        // Durations too long for the firmware are clamped, i.e. about 58000 years.
+        fn ticks(d: Duration) -> u64 {
+            d.as_secs().saturating_mul(10_000_000).saturating_add((d.subsec_nanos() / 100) as u64)
+        }
+
+        match self {
+            Timer::Cancel => (0, 0),
+            Timer::Periodic(d) => (1, ticks(d)),
+            Timer::Relative(d) => (2, ticks(d)),
+        }
+    }
+}
+
+pub(crate) type Notify = Box<dyn FnMut()>;
+
+/// Calls the closure registered with the event.
+#[cfg(target_arch = "x86_64")]
+pub(crate) unsafe extern "win64" fn notify_trampoline(_event: efi_types::EFI_EVENT, context: *mut efi_types::VOID) {
+    let notify = &mut *(context as *mut Notify);
+    notify();
+}
+
+/// Calls the closure registered with the event.
+#[cfg(not(target_arch = "x86_64"))]
+pub(crate) unsafe extern "C" fn notify_trampoline(_event: efi_types::EFI_EVENT, context: *mut efi_types::VOID) {
+    let notify = &mut *(context as *mut Notify);
+    notify();
+}
+
+/// An event created with `BootServices::create_event()`, closed on drop.
+pub struct Event {
+    raw: efi_types::EFI_EVENT,
+    // Double boxed, so that the firmware gets a thin pointer as the context.
+    notify: Option<*mut Notify>,
+}
+
+impl Event {
+    pub(crate) unsafe fn from_raw(raw: efi_types::EFI_EVENT, notify: Option<*mut Notify>) -> Event {
+        Event{ raw: raw, notify: notify }
+    }
+
+    pub fn as_raw(&self) -> efi_types::EFI_EVENT {
+        self.raw
+    }
+
+    /// Frees the notification function of an event that was already closed,
+    /// and makes sure it isn't closed again.
+    pub(crate) fn forget_closed(mut self) {
+        // The notification function can only be freed once the firmware can't call it anymore.
+        if let Some(notify) = self.notify.take() {
+            unsafe { drop(Box::from_raw(notify)) };
+        }
+        self.raw = ptr::null_mut();
+    }
+}
+
+impl Drop for Event {
+    fn drop(&mut self) {
+        if self.raw.is_null() {
+            return;
+        }
+
+        let closed = unsafe {
+            match globals::BOOT_SERVICES_TABLE {
+                Some(table) => table.close_event_raw(self.raw).is_ok(),
+                None => false,
+            }
+        };
+
+        if closed {
+            if let Some(notify) = self.notify.take() {
+                unsafe { drop(Box::from_raw(notify)) };
+            }
+        }
+        // Otherwise the firmware may still call the notification function, so it's leaked.
+    }
+}
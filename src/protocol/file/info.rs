@@ -0,0 +1,296 @@
+use alloc::vec::Vec;
+
+use protocol::Guid;
+use protocol::bytes::{read_u16, read_u32, read_u64, write_u16, write_u32, write_u64};
+use string::{CStr16, CString16};
+use super::FileAttribute;
+
+/// Information that can be queried with `File::get_info()` and changed with `File::set_info()`.
+///
+/// The firmware structures end with a variable-length string, so they are
+/// converted to and from owned values.
+pub trait InfoType: Sized {
+    const GUID: Guid;
+
+    /// Decodes the structure returned by `GetInfo()`, or `None` if it's malformed.
+    fn from_bytes(buf: &[u8]) -> Option<Self>;
+
+    /// Encodes the structure passed to `SetInfo()`.
+    fn to_bytes(&self) -> Vec<u8>;
+}
+
+/// Date and time as used by the firmware (`EFI_TIME`).
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Time {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pub nanosecond: u32,
+    /// Offset from UTC in minutes, or `Time::UNSPECIFIED_TIMEZONE` for local time.
+    pub time_zone: i16,
+    pub daylight: u8,
+}
+
+impl Time {
+    pub const UNSPECIFIED_TIMEZONE: i16 = 0x07ff;
+
+    const SIZE: usize = 16;
+
+    fn read(b: &[u8]) -> Time {
+        Time {
+            year: read_u16(b, 0),
+            month: b[2],
+            day: b[3],
+            hour: b[4],
+            minute: b[5],
+            second: b[6],
+            nanosecond: read_u32(b, 8),
+            time_zone: read_u16(b, 12) as i16,
+            daylight: b[14],
+        }
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        write_u16(out, self.year);
+        out.extend_from_slice(&[self.month, self.day, self.hour, self.minute, self.second, 0]);
+        write_u32(out, self.nanosecond);
+        write_u16(out, self.time_zone as u16);
+        out.extend_from_slice(&[self.daylight, 0]);
+    }
+}
+
+/// Information about a file (`EFI_FILE_INFO`), also returned for each directory entry.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileInfo {
+    pub file_size: u64,
+    /// Space the file takes on the volume.
+    pub physical_size: u64,
+    pub create_time: Time,
+    pub last_access_time: Time,
+    pub modification_time: Time,
+    pub attribute: FileAttribute,
+    pub file_name: CString16,
+}
+
+impl FileInfo {
+    pub fn is_directory(&self) -> bool {
+        self.attribute.contains(FileAttribute::DIRECTORY)
+    }
+}
+
+/// Information about the volume a file is on (`EFI_FILE_SYSTEM_INFO`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileSystemInfo {
+    pub read_only: bool,
+    pub volume_size: u64,
+    pub free_space: u64,
+    pub block_size: u32,
+    pub volume_label: CString16,
+}
+
+/// Label of the volume a file is on (`EFI_FILE_SYSTEM_VOLUME_LABEL`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileSystemVolumeLabel {
+    pub volume_label: CString16,
+}
+
+/// Reads the NUL-terminated string at the end of a structure.
+fn read_string(b: &[u8]) -> Option<CString16> {
+    let mut units: Vec<u16> = (0..b.len() / 2).map(|i| read_u16(b, i * 2)).collect();
+    let len = match units.iter().position(|&c| c == 0) {
+        Some(len) => len,
+        None => return None,
+    };
+    units.truncate(len + 1);
+    CString16::from_vec_with_nul(units).ok()
+}
+
+fn write_string(out: &mut Vec<u8>, s: &CStr16) {
+    for &unit in s.as_slice_with_nul() {
+        write_u16(out, unit);
+    }
+}
+
+/// Writes the `Size` field at the start of a structure, once it's complete.
+fn finish(mut out: Vec<u8>) -> Vec<u8> {
+    let size = out.len() as u64;
+    for i in 0..8 {
+        out[i] = (size >> (i * 8)) as u8;
+    }
+    out
+}
+
+impl InfoType for FileInfo {
+    const GUID: Guid = Guid(0x09576e92,0x6d3f,0x11d2,[0x8e,0x39,0x00,0xa0,0xc9,0x69,0x72,0x3b]);
+
+    fn from_bytes(b: &[u8]) -> Option<FileInfo> {
+        const NAME_OFFSET: usize = 80;
+        if b.len() < NAME_OFFSET {
+            return None;
+        }
+        let file_name = match read_string(&b[NAME_OFFSET..]) {
+            Some(name) => name,
+            None => return None,
+        };
+        Some(FileInfo {
+            file_size: read_u64(b, 8),
+            physical_size: read_u64(b, 16),
+            create_time: Time::read(&b[24..24 + Time::SIZE]),
+            last_access_time: Time::read(&b[40..40 + Time::SIZE]),
+            modification_time: Time::read(&b[56..56 + Time::SIZE]),
+            attribute: FileAttribute::from_bits(read_u64(b, 72)),
+            file_name: file_name,
+        })
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_u64(&mut out, 0);
+        write_u64(&mut out, self.file_size);
+        write_u64(&mut out, self.physical_size);
+        self.create_time.write(&mut out);
+        self.last_access_time.write(&mut out);
+        self.modification_time.write(&mut out);
+        write_u64(&mut out, self.attribute.bits());
+        write_string(&mut out, &self.file_name);
+        finish(out)
+    }
+}
+
+impl InfoType for FileSystemInfo {
+    const GUID: Guid = Guid(0x09576e93,0x6d3f,0x11d2,[0x8e,0x39,0x00,0xa0,0xc9,0x69,0x72,0x3b]);
+
+    fn from_bytes(b: &[u8]) -> Option<FileSystemInfo> {
+        const LABEL_OFFSET: usize = 36;
+        if b.len() < LABEL_OFFSET {
+            return None;
+        }
+        let volume_label = match read_string(&b[LABEL_OFFSET..]) {
+            Some(label) => label,
+            None => return None,
+        };
+        Some(FileSystemInfo {
+            read_only: b[8] != 0,
+            volume_size: read_u64(b, 16),
+            free_space: read_u64(b, 24),
+            block_size: read_u32(b, 32),
+            volume_label: volume_label,
+        })
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_u64(&mut out, 0);
+        out.extend_from_slice(&[self.read_only as u8, 0, 0, 0, 0, 0, 0, 0]);
+        write_u64(&mut out, self.volume_size);
+        write_u64(&mut out, self.free_space);
+        write_u32(&mut out, self.block_size);
+        write_string(&mut out, &self.volume_label);
+        finish(out)
+    }
+}
+
+impl InfoType for FileSystemVolumeLabel {
+    const GUID: Guid = Guid(0xdb47d7d3,0xfe81,0x11d3,[0x9a,0x35,0x00,0x90,0x27,0x3f,0xc1,0x4d]);
+
+    fn from_bytes(b: &[u8]) -> Option<FileSystemVolumeLabel> {
+        read_string(b).map(|label| FileSystemVolumeLabel{ volume_label: label })
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_string(&mut out, &self.volume_label);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use protocol::bytes::{read_u16, read_u32, read_u64};
+    use string::CString16;
+    use super::super::FileAttribute;
+    use super::*;
+
+    fn time(second: u8) -> Time {
+        Time{ year: 2017, month: 12, day: 24, hour: 18, minute: 30, second: second, nanosecond: 500_000_000,
+              time_zone: -60, daylight: 1 }
+    }
+
+    #[test]
+    fn time_layout() {
+        let mut out = Vec::new();
+        time(5).write(&mut out);
+        assert_eq!(out.len(), Time::SIZE);
+        assert_eq!(read_u16(&out, 0), 2017);
+        assert_eq!(&out[2..8], &[12, 24, 18, 30, 5, 0]);
+        assert_eq!(read_u32(&out, 8), 500_000_000);
+        assert_eq!(read_u16(&out, 12), (-60i16) as u16);
+        assert_eq!(&out[14..16], &[1, 0]);
+        assert_eq!(Time::read(&out), time(5));
+    }
+
+    #[test]
+    fn file_info_layout() {
+        let info = FileInfo {
+            file_size: 0x1234,
+            physical_size: 0x2000,
+            create_time: time(1),
+            last_access_time: time(2),
+            modification_time: time(3),
+            attribute: FileAttribute::ARCHIVE | FileAttribute::READ_ONLY,
+            file_name: CString16::new("BOOTX64.EFI").unwrap(),
+        };
+        let bytes = info.to_bytes();
+        assert_eq!(bytes.len(), 80 + 12 * 2);
+        assert_eq!(read_u64(&bytes, 0), bytes.len() as u64);
+        assert_eq!(read_u64(&bytes, 8), 0x1234);
+        assert_eq!(read_u64(&bytes, 16), 0x2000);
+        assert_eq!(bytes[24 + 6], 1);
+        assert_eq!(bytes[40 + 6], 2);
+        assert_eq!(bytes[56 + 6], 3);
+        assert_eq!(read_u64(&bytes, 72), 0x21);
+        assert_eq!(read_u16(&bytes, 80), 'B' as u16);
+        assert_eq!(read_u16(&bytes, bytes.len() - 2), 0);
+        assert_eq!(FileInfo::from_bytes(&bytes), Some(info));
+    }
+
+    #[test]
+    fn file_system_info_layout() {
+        let info = FileSystemInfo {
+            read_only: true,
+            volume_size: 0x4000_0000,
+            free_space: 0x1000_0000,
+            block_size: 512,
+            volume_label: CString16::new("EFI").unwrap(),
+        };
+        let bytes = info.to_bytes();
+        assert_eq!(bytes.len(), 36 + 4 * 2);
+        assert_eq!(read_u64(&bytes, 0), bytes.len() as u64);
+        assert_eq!(bytes[8], 1);
+        assert_eq!(read_u64(&bytes, 16), 0x4000_0000);
+        assert_eq!(read_u64(&bytes, 24), 0x1000_0000);
+        assert_eq!(read_u32(&bytes, 32), 512);
+        assert_eq!(read_u16(&bytes, 36), 'E' as u16);
+        assert_eq!(FileSystemInfo::from_bytes(&bytes), Some(info));
+    }
+
+    #[test]
+    fn malformed_info() {
+        let bytes = FileInfo {
+            file_size: 0,
+            physical_size: 0,
+            create_time: Time::default(),
+            last_access_time: Time::default(),
+            modification_time: Time::default(),
+            attribute: FileAttribute::empty(),
+            file_name: CString16::new("a").unwrap(),
+        }.to_bytes();
+        // Too short, and missing the terminator.
+        assert_eq!(FileInfo::from_bytes(&bytes[..79]), None);
+        assert_eq!(FileInfo::from_bytes(&bytes[..82]), None);
+        assert!(FileInfo::from_bytes(&bytes).is_some());
+    }
+}
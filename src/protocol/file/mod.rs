@@ -0,0 +1,481 @@
+use efi_types;
+use core::iter::FusedIterator;
+use core::mem;
+use core::ops::BitOr;
+use core::ptr;
+
+use alloc::vec::Vec;
+
+use globals;
+use protocol::{Error, Guid, Result, Status, status_to_result, status_to_status};
+use string::{CStr16, CString16};
+
+mod info;
+
+pub use self::info::{FileInfo, FileSystemInfo, FileSystemVolumeLabel, InfoType, Time};
+
+/// Access to the file systems the firmware supports, usually FAT,
+/// installed on the handle of each volume.
+///
+/// ```ignore
+/// let loaded_image = ctx.loaded_image()?;
+/// let device = loaded_image.device_handle().ok_or(Error::NOT_FOUND)?;
+/// let mut fs = ctx.boot_services().open_protocol::<SimpleFileSystem>(device, OpenProtocolAttributes::GetProtocol)?;
+/// let mut config = fs.open_volume()?.open("\\EFI\\loader\\loader.conf", FileMode::Read, FileAttribute::empty())?;
+/// let contents = config.read_to_end()?;
+/// ```
+pub struct SimpleFileSystem {
+    interface: efi_types::EFI_FILE_IO_INTERFACE,
+}
+
+unsafe impl ::protocol::Protocol for SimpleFileSystem {
+    const GUID: Guid = Guid(0x964e5b22,0x6459,0x11d2,[0x8e,0x39,0x00,0xa0,0xc9,0x69,0x72,0x3b]);
+}
+
+impl SimpleFileSystem {
+    /// Opens the root directory of the volume.
+    ///
+    /// **Errors**
+    ///
+    /// * `EFI_UNSUPPORTED`
+    ///     * The volume does not contain a file system the firmware recognizes.
+    ///
+    /// * `EFI_NO_MEDIA`
+    ///     * The device has no medium.
+    ///
+    /// * `EFI_MEDIA_CHANGED`
+    ///     * The medium changed, or the volume is no longer supported.
+    ///
+    /// * `EFI_ACCESS_DENIED`
+    ///     * The file system could not be accessed.
+    ///
+    pub fn open_volume(&mut self) -> Result<File> {
+        let mut root = ptr::null_mut();
+        let func = self.interface.OpenVolume.unwrap();
+        let status = unsafe { func(&mut self.interface, &mut root) };
+        status_to_result(status, ())?;
+        Ok(unsafe { File::from_raw(root as *mut efi_types::EFI_FILE) })
+    }
+}
+
+/// How `File::open()` opens a file.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FileMode {
+    Read,
+    ReadWrite,
+    /// Creates the file if it doesn't exist, with the attributes passed to `open()`.
+    CreateReadWrite,
+}
+
+impl FileMode {
+    fn bits(self) -> u64 {
+        match self {
+            FileMode::Read => 0x0000000000000001,
+            FileMode::ReadWrite => 0x0000000000000003,
+            FileMode::CreateReadWrite => 0x8000000000000003,
+        }
+    }
+}
+
+/// Attributes of a file, as found in `FileInfo::attribute`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FileAttribute(u64);
+
+impl FileAttribute {
+    pub const READ_ONLY: FileAttribute = FileAttribute(0x0000000000000001);
+    pub const HIDDEN: FileAttribute = FileAttribute(0x0000000000000002);
+    pub const SYSTEM: FileAttribute = FileAttribute(0x0000000000000004);
+    pub const RESERVED: FileAttribute = FileAttribute(0x0000000000000008);
+    pub const DIRECTORY: FileAttribute = FileAttribute(0x0000000000000010);
+    pub const ARCHIVE: FileAttribute = FileAttribute(0x0000000000000020);
+
+    #[inline]
+    pub const fn empty() -> Self {
+        FileAttribute(0)
+    }
+
+    #[inline]
+    pub const fn from_bits(bits: u64) -> Self {
+        FileAttribute(bits)
+    }
+
+    #[inline]
+    pub fn bits(self) -> u64 {
+        self.0
+    }
+
+    #[inline]
+    pub fn contains(self, other: FileAttribute) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for FileAttribute {
+    type Output = FileAttribute;
+
+    #[inline]
+    fn bitor(self, other: FileAttribute) -> FileAttribute {
+        FileAttribute(self.0 | other.0)
+    }
+}
+
+/// Position for `File::seek()`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SeekFrom {
+    Start(u64),
+    End(i64),
+    Current(i64),
+}
+
+/// An open file or directory, closed on drop.
+///
+/// After `ExitBootServices()` the file protocol is gone, so a dropped `File` is left alone.
+pub struct File {
+    raw: *mut efi_types::EFI_FILE,
+}
+
+impl File {
+    /// Takes ownership of a file handle returned by the firmware.
+    pub unsafe fn from_raw(raw: *mut efi_types::EFI_FILE) -> File {
+        File{ raw: raw }
+    }
+
+    pub fn as_raw(&self) -> *mut efi_types::EFI_FILE {
+        self.raw
+    }
+
+    fn interface(&self) -> &efi_types::EFI_FILE {
+        unsafe { &*self.raw }
+    }
+
+    /// Opens a file relative to this directory.
+    ///
+    /// Components of `path` are separated by `\`. A leading `\` starts
+    /// at the root of the volume, and `..` refers to the parent directory.
+    ///
+    /// **Errors**
+    ///
+    /// * `EFI_NOT_FOUND`
+    ///     * The file does not exist, and `mode` is not `CreateReadWrite`.
+    ///
+    /// * `EFI_WRITE_PROTECTED`
+    ///     * Writing was requested on a read-only volume or file.
+    ///
+    /// * `EFI_ACCESS_DENIED`
+    ///     * The file could not be opened in `mode`.
+    ///
+    /// * `EFI_VOLUME_FULL`
+    ///     * The file could not be created.
+    ///
+    /// * `EFI_INVALID_PARAMETER`
+    ///     * `path` contains a NUL.
+    ///
+    pub fn open(&mut self, path: &str, mode: FileMode, attributes: FileAttribute) -> Result<File> {
+        let path = CString16::new(path).map_err(|_| Error::INVALID_PARAMETER)?;
+        self.open_cstr16(&path, mode, attributes)
+    }
+
+    /// Same as `open()`, for a path that is already UCS-2.
+    pub fn open_cstr16(&mut self, path: &CStr16, mode: FileMode, attributes: FileAttribute) -> Result<File> {
+        let mut file = ptr::null_mut();
+        let func = self.interface().Open.unwrap();
+        let status = unsafe { func(self.raw, &mut file, path.as_ptr() as *mut _, mode.bits(), attributes.bits()) };
+        status_to_result(status, ())?;
+        Ok(unsafe { File::from_raw(file as *mut efi_types::EFI_FILE) })
+    }
+
+    /// Reads from the current position, and returns the number of bytes read.
+    ///
+    /// Returns 0 at the end of the file. For directories, use `read_entry()`.
+    ///
+    /// **Errors**
+    ///
+    /// * `EFI_NO_MEDIA`
+    ///     * The device has no medium.
+    ///
+    /// * `EFI_DEVICE_ERROR`
+    ///     * The device reported an error, or the position is past the end of the file.
+    ///
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let mut size = buf.len();
+        let func = self.interface().Read.unwrap();
+        let status = unsafe { func(self.raw, &mut size as *mut usize as *mut _, buf.as_mut_ptr() as *mut _) };
+        status_to_result(status, size)
+    }
+
+    /// Reads the rest of the file.
+    pub fn read_to_end(&mut self) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        let mut chunk = [0u8; 4096];
+        loop {
+            let len = self.read(&mut chunk)?;
+            if len == 0 {
+                return Ok(data);
+            }
+            data.extend_from_slice(&chunk[..len]);
+        }
+    }
+
+    /// Reads the next directory entry, or `None` after the last one.
+    ///
+    /// **Errors**
+    ///
+    /// * `EFI_DEVICE_ERROR`
+    ///     * The device reported an error, or the file is not a directory.
+    ///
+    pub fn read_entry(&mut self) -> Result<Option<FileInfo>> {
+        // Enough for most names, the firmware tells us if it isn't.
+        let mut buffer: Vec<u64> = Vec::new();
+        let mut size = 128 + 80;
+        loop {
+            buffer.resize((size + 7) / 8, 0);
+            let capacity = buffer.len() * 8;
+            size = capacity;
+            let func = self.interface().Read.unwrap();
+            let status = unsafe { func(self.raw, &mut size as *mut usize as *mut _, buffer.as_mut_ptr() as *mut _) };
+            match status_to_result(status, ()) {
+                Ok(()) => break,
+                Err(ref e) if *e == Error::BUFFER_TOO_SMALL && size > capacity => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
+        if size == 0 {
+            return Ok(None);
+        }
+        let bytes = unsafe { ::core::slice::from_raw_parts(buffer.as_ptr() as *const u8, size) };
+        FileInfo::from_bytes(bytes).map(Some).ok_or(Error::VOLUME_CORRUPTED)
+    }
+
+    /// Iterates over the remaining entries of a directory, including `.` and `..`.
+    pub fn entries(&mut self) -> Entries {
+        Entries{ dir: self, done: false }
+    }
+
+    /// Writes at the current position, extending the file if needed.
+    ///
+    /// **Errors**
+    ///
+    /// * `EFI_WRITE_PROTECTED`
+    ///     * The file or volume is read-only.
+    ///
+    /// * `EFI_ACCESS_DENIED`
+    ///     * The file was opened read-only.
+    ///
+    /// * `EFI_VOLUME_FULL`
+    ///     * The volume is full.
+    ///
+    /// * `EFI_UNSUPPORTED`
+    ///     * The file is a directory.
+    ///
+    pub fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let mut size = buf.len();
+        let func = self.interface().Write.unwrap();
+        let status = unsafe { func(self.raw, &mut size as *mut usize as *mut _, buf.as_ptr() as *mut _) };
+        status_to_result(status, size)
+    }
+
+    /// Writes the whole buffer.
+    pub fn write_all(&mut self, mut buf: &[u8]) -> Result<()> {
+        while !buf.is_empty() {
+            let len = self.write(buf)?;
+            if len == 0 {
+                return Err(Error::VOLUME_FULL);
+            }
+            buf = &buf[len..];
+        }
+        Ok(())
+    }
+
+    /// Returns the current position.
+    ///
+    /// **Errors**
+    ///
+    /// * `EFI_UNSUPPORTED`
+    ///     * The file is a directory.
+    ///
+    pub fn position(&mut self) -> Result<u64> {
+        let mut position = 0;
+        let func = self.interface().GetPosition.unwrap();
+        let status = unsafe { func(self.raw, &mut position) };
+        status_to_result(status, position as u64)
+    }
+
+    /// Changes the current position, and returns the new one.
+    ///
+    /// Positions past the end are allowed, and writing there extends the file.
+    /// On a directory, only `SeekFrom::Start(0)` is supported, to restart `read_entry()`.
+    ///
+    /// **Errors**
+    ///
+    /// * `EFI_INVALID_PARAMETER`
+    ///     * The position would be negative.
+    ///
+    /// * `EFI_UNSUPPORTED`
+    ///     * The file is a directory, and the position is not 0.
+    ///
+    pub fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => {
+                let size = self.get_info::<FileInfo>()?.file_size;
+                offset_position(size, offset)
+            },
+            SeekFrom::Current(offset) => {
+                let current = self.position()?;
+                offset_position(current, offset)
+            },
+        };
+        let position = position.ok_or(Error::INVALID_PARAMETER)?;
+
+        let func = self.interface().SetPosition.unwrap();
+        let status = unsafe { func(self.raw, position as _) };
+        status_to_result(status, position)
+    }
+
+    /// Writes cached data to the device.
+    ///
+    /// **Errors**
+    ///
+    /// * `EFI_WRITE_PROTECTED`
+    ///     * The file or volume is read-only.
+    ///
+    /// * `EFI_VOLUME_FULL`
+    ///     * The volume is full.
+    ///
+    pub fn flush(&mut self) -> Result<()> {
+        let func = self.interface().Flush.unwrap();
+        let status = unsafe { func(self.raw) };
+        status_to_result(status, ())
+    }
+
+    /// Deletes the file, closing it.
+    ///
+    /// Returns `Status::WARN_DELETE_FAILURE` if the file was closed, but not deleted.
+    pub fn delete(self) -> Result<Status> {
+        let raw = self.raw;
+        mem::forget(self);
+        let func = unsafe { (*raw).Delete.unwrap() };
+        let status = unsafe { func(raw) };
+        status_to_status(status)
+    }
+
+    /// Closes the file. Dropping it does the same.
+    pub fn close(self) {}
+
+    /// Queries information about the file, or the volume it's on.
+    ///
+    /// ```ignore
+    /// let size = file.get_info::<FileInfo>()?.file_size;
+    /// ```
+    ///
+    /// **Errors**
+    ///
+    /// * `EFI_UNSUPPORTED`
+    ///     * The information type is not supported.
+    ///
+    /// * `EFI_VOLUME_CORRUPTED`
+    ///     * The returned structure is malformed.
+    ///
+    pub fn get_info<T: InfoType>(&mut self) -> Result<T> {
+        let mut buffer: Vec<u64> = Vec::new();
+        let mut size = 0;
+        loop {
+            buffer.resize((size + 7) / 8, 0);
+            let capacity = buffer.len() * 8;
+            size = capacity;
+            let func = self.interface().GetInfo.unwrap();
+            let status = unsafe { func(self.raw, &T::GUID as *const Guid as *mut _,
+                                       &mut size as *mut usize as *mut _, buffer.as_mut_ptr() as *mut _) };
+            match status_to_result(status, ()) {
+                Ok(()) => break,
+                Err(ref e) if *e == Error::BUFFER_TOO_SMALL && size > capacity => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
+        let bytes = unsafe { ::core::slice::from_raw_parts(buffer.as_ptr() as *const u8, size) };
+        T::from_bytes(bytes).ok_or(Error::VOLUME_CORRUPTED)
+    }
+
+    /// Changes information about the file, or the volume it's on.
+    ///
+    /// For `FileInfo`, this can rename the file, change its attributes
+    /// and times, and truncate or extend it by changing `file_size`.
+    ///
+    /// **Errors**
+    ///
+    /// * `EFI_UNSUPPORTED`
+    ///     * The information type is not supported.
+    ///
+    /// * `EFI_WRITE_PROTECTED`
+    ///     * The file or volume is read-only.
+    ///
+    /// * `EFI_ACCESS_DENIED`
+    ///     * The change is not allowed, e.g. changing the `DIRECTORY` attribute,
+    ///       or changing the size of a file opened read-only.
+    ///
+    pub fn set_info<T: InfoType>(&mut self, info: &T) -> Result<()> {
+        let bytes = info.to_bytes();
+        // Copy to an aligned buffer, as the firmware accesses the fields directly.
+        let mut buffer: Vec<u64> = Vec::new();
+        buffer.resize((bytes.len() + 7) / 8, 0);
+        unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), buffer.as_mut_ptr() as *mut u8, bytes.len()) };
+
+        let func = self.interface().SetInfo.unwrap();
+        let status = unsafe { func(self.raw, &T::GUID as *const Guid as *mut _, bytes.len() as _,
+                                   buffer.as_mut_ptr() as *mut _) };
+        status_to_result(status, ())
+    }
+}
+
+/// Adds a signed offset to a position, or returns `None` if it would be negative.
+fn offset_position(base: u64, offset: i64) -> Option<u64> {
+    if offset >= 0 {
+        base.checked_add(offset as u64)
+    } else {
+        base.checked_sub(offset.wrapping_neg() as u64)
+    }
+}
+
+impl Drop for File {
+    fn drop(&mut self) {
+        if unsafe { globals::BOOT_SERVICES_TABLE.is_none() } {
+            return;
+        }
+        let func = self.interface().Close.unwrap();
+        let _ = unsafe { func(self.raw) };
+    }
+}
+
+/// Iterator over directory entries, returned by `File::entries()`.
+///
+/// Ends after the first error.
+pub struct Entries<'a> {
+    dir: &'a mut File,
+    done: bool,
+}
+
+impl<'a> Iterator for Entries<'a> {
+    type Item = Result<FileInfo>;
+
+    fn next(&mut self) -> Option<Result<FileInfo>> {
+        if self.done {
+            return None;
+        }
+        match self.dir.read_entry() {
+            Ok(Some(info)) => Some(Ok(info)),
+            Ok(None) => {
+                self.done = true;
+                None
+            },
+            Err(e) => {
+                // The position is unknown after an error, so reading on could loop forever.
+                self.done = true;
+                Some(Err(e))
+            },
+        }
+    }
+}
+
+impl<'a> FusedIterator for Entries<'a> {}
@@ -0,0 +1,140 @@
+use efi_types;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+
+use protocol::Guid;
+use protocol::boot_services::BootServices;
+
+/// Handle to a collection of protocol interfaces, such as a device or an image.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Handle(efi_types::EFI_HANDLE);
+
+impl Handle {
+    pub unsafe fn from_raw(raw: efi_types::EFI_HANDLE) -> Handle {
+        Handle(raw)
+    }
+
+    pub fn as_raw(self) -> efi_types::EFI_HANDLE {
+        self.0
+    }
+}
+
+/// Which handles `BootServices::locate_handle_buffer()` returns.
+pub enum SearchType<'a> {
+    /// Every handle in the system.
+    AllHandles,
+    /// Handles that had a protocol installed since the last search with this registration.
+    ByRegisterNotify(&'a Registration),
+    /// Handles that support the protocol.
+    ByProtocol(&'a Guid),
+}
+
+/// Registration returned by `BootServices::register_protocol_notify()`.
+pub struct Registration(pub(crate) *mut efi_types::VOID);
+
+/// How a protocol interface is opened by `BootServices::open_protocol()`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OpenProtocolAttributes {
+    /// Same as `HandleProtocol()`, kept for compatibility with older drivers.
+    ByHandleProtocol,
+    /// Opens the interface without being tracked as its user.
+    /// The interface may be uninstalled while in use.
+    GetProtocol,
+    /// Opens the interface for exclusive use, disconnecting drivers that use it.
+    Exclusive,
+}
+
+impl OpenProtocolAttributes {
+    pub(crate) fn bits(self) -> u32 {
+        match self {
+            OpenProtocolAttributes::ByHandleProtocol => 0x01,
+            OpenProtocolAttributes::GetProtocol => 0x02,
+            OpenProtocolAttributes::Exclusive => 0x20,
+        }
+    }
+}
+
+/// An open protocol interface, closed with `CloseProtocol()` on drop.
+pub struct ProtocolRef<'a, P: 'a> {
+    boot_services: &'a BootServices,
+    interface: *mut P,
+    handle: Handle,
+    guid: Guid,
+    agent: Handle,
+    controller: Option<Handle>,
+    _marker: PhantomData<&'a mut P>,
+}
+
+impl<'a, P: 'a> ProtocolRef<'a, P> {
+    pub(crate) unsafe fn new(boot_services: &'a BootServices, interface: *mut P, handle: Handle, guid: Guid,
+                             agent: Handle, controller: Option<Handle>) -> ProtocolRef<'a, P> {
+        ProtocolRef {
+            boot_services: boot_services,
+            interface: interface,
+            handle: handle,
+            guid: guid,
+            agent: agent,
+            controller: controller,
+            _marker: PhantomData,
+        }
+    }
+
+    /// The handle the protocol was opened on.
+    pub fn handle(&self) -> Handle {
+        self.handle
+    }
+}
+
+impl<'a, P: 'a> Deref for ProtocolRef<'a, P> {
+    type Target = P;
+
+    fn deref(&self) -> &P {
+        unsafe { &*self.interface }
+    }
+}
+
+impl<'a, P: 'a> DerefMut for ProtocolRef<'a, P> {
+    fn deref_mut(&mut self) -> &mut P {
+        unsafe { &mut *self.interface }
+    }
+}
+
+impl<'a, P: 'a> Drop for ProtocolRef<'a, P> {
+    fn drop(&mut self) {
+        let _ = unsafe { self.boot_services.close_protocol(self.handle, &self.guid, self.agent, self.controller) };
+    }
+}
+
+/// Protocols installed on a handle, returned by `BootServices::protocols_per_handle()`.
+pub struct ProtocolGuids {
+    pub(crate) guids: ::protocol::pool::PoolSlice<*const Guid>,
+}
+
+impl ProtocolGuids {
+    pub fn len(&self) -> usize {
+        self.guids.len()
+    }
+
+    pub fn iter(&self) -> ProtocolGuidsIter {
+        ProtocolGuidsIter{ guids: &self.guids }
+    }
+}
+
+pub struct ProtocolGuidsIter<'a> {
+    guids: &'a [*const Guid],
+}
+
+impl<'a> Iterator for ProtocolGuidsIter<'a> {
+    type Item = &'a Guid;
+
+    fn next(&mut self) -> Option<&'a Guid> {
+        match self.guids.split_first() {
+            Some((&first, rest)) => {
+                self.guids = rest;
+                Some(unsafe { &*first })
+            },
+            None => None,
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use core::mem;
+
+use globals;
+use protocol::device_path::DevicePath;
+use protocol::handle::{Handle, OpenProtocolAttributes};
+use protocol::loaded_image::LoadedImage;
+use protocol::pool::PoolSlice;
+use protocol::{Error, Result, Status};
+use string::{CStr16, CString16};
+
+/// Where `BootServices::load_image()` gets the image from.
+pub enum ImageSource<'a> {
+    /// Loads the file at `device_path`.
+    ///
+    /// `boot_policy` is `true` if the request comes from the boot manager.
+    /// A device path to a removable media device is then resolved to its default
+    /// boot file, such as `\EFI\BOOT\BOOTX64.EFI`.
+    DevicePath { device_path: &'a DevicePath, boot_policy: bool },
+    /// Loads the image from memory.
+    ///
+    /// `device_path`, if given, is recorded as the file the image was loaded from.
+    Buffer { data: &'a [u8], device_path: Option<&'a DevicePath> },
+}
+
+/// An image loaded with `BootServices::load_image()`.
+///
+/// Unloaded on drop, unless it was started.
+pub struct Image {
+    handle: Handle,
+    started: bool,
+    load_options: Option<CString16>,
+}
+
+/// How an image started with `BootServices::start_image()` exited.
+pub struct ImageExit {
+    /// The status the image passed to `Exit()`, or returned from its entry point.
+    pub status: Result<Status>,
+    /// Data the image passed to `Exit()`, if any.
+    pub exit_data: Option<ExitData>,
+}
+
+/// Data passed to `Exit()`: a NUL-terminated description,
+/// optionally followed by binary data.
+pub struct ExitData {
+    data: PoolSlice<u16>,
+}
+
+impl Image {
+    pub(crate) fn new(handle: Handle) -> Image {
+        Image{ handle: handle, started: false, load_options: None }
+    }
+
+    pub fn handle(&self) -> Handle {
+        self.handle
+    }
+
+    /// Sets the command line passed to the image in its `LoadedImage` protocol.
+    ///
+    /// The options are freed when the image is dropped, unless it was started:
+    /// a driver that stays resident can read them at any time.
+    pub fn set_load_options(&mut self, options: CString16) -> Result<()> {
+        let boot_services = unsafe { globals::BOOT_SERVICES_TABLE.ok_or(Error::UNSUPPORTED)? };
+        let mut loaded_image = boot_services.open_protocol::<LoadedImage>(
+            self.handle, OpenProtocolAttributes::GetProtocol)?;
+
+        {
+            let units = options.as_slice_with_nul();
+            unsafe { loaded_image.set_load_options_raw(units.as_ptr() as *const u8, units.len() * 2) };
+        }
+        self.load_options = Some(options);
+        Ok(())
+    }
+
+    /// Whether the image was passed to `BootServices::start_image()`.
+    pub fn is_started(&self) -> bool {
+        self.started
+    }
+
+    pub(crate) fn set_started(&mut self) {
+        self.started = true;
+    }
+}
+
+impl Drop for Image {
+    fn drop(&mut self) {
+        // Started images are either gone or resident drivers.
+        if self.started {
+            mem::forget(self.load_options.take());
+            return;
+        }
+
+        unsafe {
+            if let Some(table) = globals::BOOT_SERVICES_TABLE {
+                let _ = table.unload_image_raw(self.handle);
+            }
+        }
+    }
+}
+
+impl ExitData {
+    pub(crate) unsafe fn from_raw(ptr: *mut u16, size: usize) -> ExitData {
+        ExitData{ data: PoolSlice::from_raw(ptr, size / 2) }
+    }
+
+    /// The description, if it's properly terminated.
+    pub fn description(&self) -> Option<&CStr16> {
+        let len = match self.data.iter().position(|&c| c == 0) {
+            Some(pos) => pos + 1,
+            None => return None,
+        };
+        CStr16::from_u16_with_nul(&self.data[..len]).ok()
+    }
+
+    /// The whole buffer, including the description and any binary data.
+    pub fn as_slice(&self) -> &[u16] {
+        &self.data
+    }
+}
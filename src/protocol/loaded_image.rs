@@ -0,0 +1,193 @@
+use efi_types;
+use core::convert::TryFrom;
+use core::mem;
+use core::slice;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use protocol::Guid;
+use protocol::boot_services::MemoryType;
+use protocol::device_path::DevicePath;
+use protocol::handle::Handle;
+use ucs2;
+
+/// Information about a loaded image, installed on every image handle.
+///
+/// Use `BootContext::loaded_image()` to get the one of the running application.
+#[repr(C)]
+pub struct LoadedImage {
+    interface: efi_types::EFI_LOADED_IMAGE,
+}
+
+unsafe impl ::protocol::Protocol for LoadedImage {
+    const GUID: Guid = Guid(0x5b1b31a1,0x9562,0x11d2,[0x8e,0x3f,0x00,0xa0,0xc9,0x69,0x72,0x3b]);
+}
+
+impl LoadedImage {
+    /// Address the image was loaded at.
+    pub fn image_base(&self) -> *const u8 {
+        self.interface.ImageBase as *const u8
+    }
+
+    /// Size of the loaded image, in bytes.
+    pub fn image_size(&self) -> u64 {
+        self.interface.ImageSize as u64
+    }
+
+    /// Memory type the code sections were loaded as, if it's a standard type.
+    pub fn image_code_type(&self) -> Option<MemoryType> {
+        MemoryType::try_from(self.interface.ImageCodeType as u32).ok()
+    }
+
+    /// Memory type the data sections were loaded as, if it's a standard type.
+    pub fn image_data_type(&self) -> Option<MemoryType> {
+        MemoryType::try_from(self.interface.ImageDataType as u32).ok()
+    }
+
+    /// The image that loaded this one, or `None` if it was loaded by the firmware itself.
+    pub fn parent_handle(&self) -> Option<Handle> {
+        let handle = self.interface.ParentHandle;
+        if handle.is_null() { None } else { Some(unsafe { Handle::from_raw(handle) }) }
+    }
+
+    /// The device the image was loaded from, e.g. the volume to open
+    /// with `SimpleFileSystem` to read files next to the image.
+    ///
+    /// `None` if the image was loaded from memory without a device path.
+    pub fn device_handle(&self) -> Option<Handle> {
+        let handle = self.interface.DeviceHandle;
+        if handle.is_null() { None } else { Some(unsafe { Handle::from_raw(handle) }) }
+    }
+
+    /// Path of the image file, relative to `device_handle()`.
+    ///
+    /// `None` if there is none, or if it's malformed.
+    pub fn file_path(&self) -> Option<&DevicePath> {
+        let path = self.interface.FilePath;
+        if path.is_null() { None } else { unsafe { DevicePath::from_ptr(path as *const u8) } }
+    }
+
+    /// The options the image was started with, as raw bytes.
+    ///
+    /// Their format is up to whoever started the image. The shell and
+    /// the boot manager usually pass a UCS-2 command line.
+    pub fn load_options(&self) -> &[u8] {
+        let p = self.interface.LoadOptions as *const u8;
+        if p.is_null() {
+            return &[];
+        }
+        unsafe { slice::from_raw_parts(p, self.interface.LoadOptionsSize as usize) }
+    }
+
+    /// The options as UCS-2 code units, up to the NUL terminator.
+    ///
+    /// Empty if the options can't be UCS-2 because of their size or alignment.
+    pub fn load_options_ucs2(&self) -> &[u16] {
+        let bytes = self.load_options();
+        if bytes.len() % 2 != 0 || bytes.as_ptr() as usize % mem::align_of::<u16>() != 0 {
+            return &[];
+        }
+        let units = unsafe { slice::from_raw_parts(bytes.as_ptr() as *const u16, bytes.len() / 2) };
+        let len = units.iter().position(|&c| c == 0).unwrap_or(units.len());
+        &units[..len]
+    }
+
+    /// Splits the options, as a UCS-2 command line, into arguments.
+    ///
+    /// Arguments are separated by spaces and tabs. Double quotes group spaces
+    /// into an argument, and `^` makes the next character literal, as in the shell.
+    /// When started from the shell, the first argument is the name of the image.
+    pub fn arguments(&self) -> Vec<String> {
+        split_arguments(self.load_options_ucs2())
+    }
+
+    /// Sets the options passed to the image, as raw bytes.
+    ///
+    /// Unsafe because the buffer must stay valid as long as the image may read it.
+    pub(crate) unsafe fn set_load_options_raw(&mut self, options: *const u8, size: usize) {
+        self.interface.LoadOptions = options as *mut _;
+        self.interface.LoadOptionsSize = size as _;
+    }
+}
+
+/// Splits a command line as described for `LoadedImage::arguments()`.
+fn split_arguments(command_line: &[u16]) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_arg = false;
+    let mut quoted = false;
+    let mut chars = ucs2::decode(command_line);
+
+    while let Some(c) = chars.next() {
+        match c {
+            '^' => {
+                if let Some(next) = chars.next() {
+                    current.push(next);
+                }
+                in_arg = true;
+            },
+            '"' => {
+                quoted = !quoted;
+                in_arg = true;
+            },
+            ' ' | '\t' if !quoted => {
+                if in_arg {
+                    args.push(mem::replace(&mut current, String::new()));
+                    in_arg = false;
+                }
+            },
+            _ => {
+                current.push(c);
+                in_arg = true;
+            },
+        }
+    }
+
+    if in_arg {
+        args.push(current);
+    }
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::String;
+    use alloc::vec::Vec;
+    use ucs2;
+    use super::split_arguments;
+
+    fn split(command_line: &str) -> Vec<String> {
+        let units: Vec<u16> = ucs2::Encoder::new(command_line, ucs2::Policy::Surrogates).collect();
+        split_arguments(&units)
+    }
+
+    #[test]
+    fn whitespace() {
+        assert_eq!(split(""), [""; 0]);
+        assert_eq!(split(" \t "), [""; 0]);
+        assert_eq!(split("app.efi  -v\t\tfile "), ["app.efi", "-v", "file"]);
+    }
+
+    #[test]
+    fn quotes() {
+        assert_eq!(split("a \"b c\" d"), ["a", "b c", "d"]);
+        assert_eq!(split("x\"y z\"w"), ["xy zw"]);
+        assert_eq!(split("\"\" a"), ["", "a"]);
+        // An unterminated quote runs to the end.
+        assert_eq!(split("a \"b  c"), ["a", "b  c"]);
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(split("a^ b ^\"c d^\""), ["a b", "\"c", "d\""]);
+        assert_eq!(split("^^ ^"), ["^", ""]);
+        assert_eq!(split("\"a^\"b\""), ["a\"b"]);
+    }
+
+    #[test]
+    fn stops_at_nul() {
+        let units = [0x61, 0x20, 0xd83d, 0xde00, 0, 0x62];
+        assert_eq!(split_arguments(&units), ["a", "\u{1f600}"]);
+    }
+}
@@ -1,37 +1,377 @@
 
+use efi_types;
 use core;
 use core::convert::TryFrom;
+use core::fmt;
+use core::str::FromStr;
+use core::usize;
 
 pub mod console;
 pub mod boot_services;
+mod bytes;
+pub mod device_path;
+pub mod event;
+pub mod file;
+pub mod handle;
+pub mod image;
+pub mod loaded_image;
+pub mod pool;
+pub mod runtime_services;
+pub mod tpl;
 
+/// Globally unique identifier, used to identify protocols, configuration tables and the like.
+#[repr(C)]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Guid(u32, u16, u16, [u8; 8]);
 
-// TODO
-#[derive(Copy, Clone, Debug)]
+/// Error returned when a string is not a GUID in registry format.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ParseGuidError;
+
+impl Guid {
+    pub const fn new(time_low: u32, time_mid: u16, time_high_and_version: u16, node: [u8; 8]) -> Guid {
+        Guid(time_low, time_mid, time_high_and_version, node)
+    }
+
+    /// Reads a GUID from its binary layout, in which the first three fields are little-endian.
+    pub fn from_bytes(b: [u8; 16]) -> Guid {
+        Guid(
+            (b[0] as u32) | (b[1] as u32) << 8 | (b[2] as u32) << 16 | (b[3] as u32) << 24,
+            (b[4] as u16) | (b[5] as u16) << 8,
+            (b[6] as u16) | (b[7] as u16) << 8,
+            [b[8], b[9], b[10], b[11], b[12], b[13], b[14], b[15]],
+        )
+    }
+
+    /// The binary layout of the GUID, in which the first three fields are little-endian.
+    pub fn to_bytes(&self) -> [u8; 16] {
+        let Guid(a, b, c, d) = *self;
+        [
+            a as u8, (a >> 8) as u8, (a >> 16) as u8, (a >> 24) as u8,
+            b as u8, (b >> 8) as u8,
+            c as u8, (c >> 8) as u8,
+            d[0], d[1], d[2], d[3], d[4], d[5], d[6], d[7],
+        ]
+    }
+
+    /// Parses a GUID in registry format, e.g. `387477c2-69c7-11d2-8e39-00a0c969723b`.
+    ///
+    /// This is a `const fn`, so that `guid!` can do the parsing at compile time.
+    pub const fn parse(s: &str) -> Option<Guid> {
+        const fn hex(c: u8) -> Option<u64> {
+            match c {
+                b'0'..=b'9' => Some((c - b'0') as u64),
+                b'a'..=b'f' => Some((c - b'a' + 10) as u64),
+                b'A'..=b'F' => Some((c - b'A' + 10) as u64),
+                _ => None,
+            }
+        }
+
+        // Parses `len` hex digits starting at `start`.
+        const fn field(s: &[u8], start: usize, len: usize) -> Option<u64> {
+            let mut value = 0;
+            let mut i = start;
+            while i < start + len {
+                value = match hex(s[i]) {
+                    Some(digit) => (value << 4) | digit,
+                    None => return None,
+                };
+                i += 1;
+            }
+            Some(value)
+        }
+
+        let s = s.as_bytes();
+        if s.len() != 36 || s[8] != b'-' || s[13] != b'-' || s[18] != b'-' || s[23] != b'-' {
+            return None;
+        }
+
+        let (a, b, c, d) = match (field(s, 0, 8), field(s, 9, 4), field(s, 14, 4), field(s, 19, 4)) {
+            (Some(a), Some(b), Some(c), Some(d)) => (a, b, c, d),
+            _ => return None,
+        };
+
+        let mut node = [(d >> 8) as u8, d as u8, 0, 0, 0, 0, 0, 0];
+        let mut i = 0;
+        while i < 6 {
+            node[i + 2] = match field(s, 24 + i * 2, 2) {
+                Some(byte) => byte as u8,
+                None => return None,
+            };
+            i += 1;
+        }
+
+        Some(Guid(a as u32, b as u16, c as u16, node))
+    }
+}
+
+/// Creates a `Guid` from a string in registry format, checked at compile time.
+///
+/// ```ignore
+/// const SIMPLE_TEXT_OUTPUT: Guid = guid!("387477c2-69c7-11d2-8e39-00a0c969723b");
+/// ```
+#[macro_export]
+macro_rules! guid {
+    ($s:expr) => {{
+        const GUID: $crate::protocol::Guid = match $crate::protocol::Guid::parse($s) {
+            Some(guid) => guid,
+            None => panic!("invalid GUID"),
+        };
+        GUID
+    }};
+}
+
+impl fmt::Display for Guid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let Guid(a, b, c, d) = *self;
+        write!(f, "{:08x}-{:04x}-{:04x}-{:02x}{:02x}-{:02x}{:02x}{:02x}{:02x}{:02x}{:02x}",
+               a, b, c, d[0], d[1], d[2], d[3], d[4], d[5], d[6], d[7])
+    }
+}
+
+impl fmt::Debug for Guid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Guid({})", self)
+    }
+}
+
+impl FromStr for Guid {
+    type Err = ParseGuidError;
+
+    /// Parses a GUID in registry format, optionally enclosed in braces.
+    fn from_str(s: &str) -> core::result::Result<Guid, ParseGuidError> {
+        let s = if s.starts_with('{') && s.ends_with('}') && s.len() >= 2 { &s[1..s.len()-1] } else { s };
+        Guid::parse(s).ok_or(ParseGuidError)
+    }
+}
+
+/// A protocol interface structure identified by a GUID.
+///
+/// Implementing this trait is unsafe, because the firmware is trusted to hand out
+/// a pointer to a `Self` for interfaces installed under `GUID`.
+/// Use `unsafe_impl_protocol!` to declare vendor protocols.
+pub unsafe trait Protocol {
+    const GUID: Guid;
+}
+
+/// Implements `Protocol` for an interface structure.
+///
+/// ```ignore
+/// #[repr(C)]
+/// pub struct MyProtocol {
+///     revision: u64,
+///     frobnicate: extern "win64" fn(*mut MyProtocol) -> usize,
+/// }
+///
+/// unsafe_impl_protocol!(MyProtocol, Guid::new(0x12345678, 0x9abc, 0xdef0, [0, 1, 2, 3, 4, 5, 6, 7]));
+/// ```
+#[macro_export]
+macro_rules! unsafe_impl_protocol {
+    ($ty:ty, $guid:expr) => {
+        unsafe impl $crate::protocol::Protocol for $ty {
+            const GUID: $crate::protocol::Guid = $guid;
+        }
+    };
+}
+
+/// Bit that distinguishes errors from warnings in `EFI_STATUS`.
+///
+/// `EFI_STATUS` is `UINTN`, so this is the top bit of a 32-bit or 64-bit value,
+/// depending on the target.
+const ERROR_BIT: usize = !(usize::MAX >> 1);
+
+/// Status code of a call that succeeded, possibly with a warning.
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
 pub struct Status {
     code: usize,
 }
 
 impl Status {
-    fn success() -> Status {
-        Status{code: 0}
+    pub const SUCCESS: Status = Status{code: 0};
+    pub const WARN_UNKNOWN_GLYPH: Status = Status{code: 1};
+    pub const WARN_DELETE_FAILURE: Status = Status{code: 2};
+    pub const WARN_WRITE_FAILURE: Status = Status{code: 3};
+    pub const WARN_BUFFER_TOO_SMALL: Status = Status{code: 4};
+    pub const WARN_STALE_DATA: Status = Status{code: 5};
+    pub const WARN_FILE_SYSTEM: Status = Status{code: 6};
+    pub const WARN_RESET_REQUIRED: Status = Status{code: 7};
+
+    pub const fn success() -> Status {
+        Status::SUCCESS
     }
 
-    fn is_success(&self) -> bool {
+    pub fn is_success(&self) -> bool {
         self.code == 0
     }
+
+    pub fn is_warning(&self) -> bool {
+        self.code != 0
+    }
+
+    /// Splits a raw `EFI_STATUS` into a status or an error.
+    pub fn from_raw(raw: efi_types::EFI_STATUS) -> core::result::Result<Status, Error> {
+        let raw = raw as usize;
+        if raw & ERROR_BIT != 0 {
+            Err(Error{code: raw & !ERROR_BIT})
+        } else {
+            Ok(Status{code: raw})
+        }
+    }
+
+    /// The raw `EFI_STATUS` value, e.g. for returning from the entry point.
+    pub fn into_raw(self) -> efi_types::EFI_STATUS {
+        self.code as _
+    }
+
+    /// Name of the status code as given in the UEFI specification.
+    pub fn name(&self) -> Option<&'static str> {
+        Some(match self.code {
+            0 => "EFI_SUCCESS",
+            1 => "EFI_WARN_UNKNOWN_GLYPH",
+            2 => "EFI_WARN_DELETE_FAILURE",
+            3 => "EFI_WARN_WRITE_FAILURE",
+            4 => "EFI_WARN_BUFFER_TOO_SMALL",
+            5 => "EFI_WARN_STALE_DATA",
+            6 => "EFI_WARN_FILE_SYSTEM",
+            7 => "EFI_WARN_RESET_REQUIRED",
+            _ => return None,
+        })
+    }
 }
 
-// TODO
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.name() {
+            Some(name) => f.write_str(name),
+            None => write!(f, "EFI_STATUS({:#x})", self.code),
+        }
+    }
+}
+
+impl fmt::Debug for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Status({})", self)
+    }
+}
+
+impl From<Status> for efi_types::EFI_STATUS {
+    fn from(status: Status) -> efi_types::EFI_STATUS {
+        status.into_raw()
+    }
+}
+
+/// Error code of a call that failed.
+///
+/// The code is stored without the error bit, so the constants are
+/// the same on 32-bit and 64-bit targets.
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
 pub struct Error {
     code: usize,
 }
 
 impl Error {
+    pub const LOAD_ERROR: Error = Error{code: 1};
+    pub const INVALID_PARAMETER: Error = Error{code: 2};
+    pub const UNSUPPORTED: Error = Error{code: 3};
+    pub const BAD_BUFFER_SIZE: Error = Error{code: 4};
+    pub const BUFFER_TOO_SMALL: Error = Error{code: 5};
+    pub const NOT_READY: Error = Error{code: 6};
+    pub const DEVICE_ERROR: Error = Error{code: 7};
+    pub const WRITE_PROTECTED: Error = Error{code: 8};
+    pub const OUT_OF_RESOURCES: Error = Error{code: 9};
+    pub const VOLUME_CORRUPTED: Error = Error{code: 10};
+    pub const VOLUME_FULL: Error = Error{code: 11};
+    pub const NO_MEDIA: Error = Error{code: 12};
+    pub const MEDIA_CHANGED: Error = Error{code: 13};
+    pub const NOT_FOUND: Error = Error{code: 14};
+    pub const ACCESS_DENIED: Error = Error{code: 15};
+    pub const NO_RESPONSE: Error = Error{code: 16};
+    pub const NO_MAPPING: Error = Error{code: 17};
+    pub const TIMEOUT: Error = Error{code: 18};
+    pub const NOT_STARTED: Error = Error{code: 19};
+    pub const ALREADY_STARTED: Error = Error{code: 20};
+    pub const ABORTED: Error = Error{code: 21};
+    pub const ICMP_ERROR: Error = Error{code: 22};
+    pub const TFTP_ERROR: Error = Error{code: 23};
+    pub const PROTOCOL_ERROR: Error = Error{code: 24};
+    pub const INCOMPATIBLE_VERSION: Error = Error{code: 25};
+    pub const SECURITY_VIOLATION: Error = Error{code: 26};
+    pub const CRC_ERROR: Error = Error{code: 27};
+    pub const END_OF_MEDIA: Error = Error{code: 28};
+    pub const END_OF_FILE: Error = Error{code: 31};
+    pub const INVALID_LANGUAGE: Error = Error{code: 32};
+    pub const COMPROMISED_DATA: Error = Error{code: 33};
+    pub const IP_ADDRESS_CONFLICT: Error = Error{code: 34};
+    pub const HTTP_ERROR: Error = Error{code: 35};
+
     pub fn invalid_parameter() -> Error {
-        // FIXME
-        Error { code: 2 }
+        Error::INVALID_PARAMETER
+    }
+
+    /// The raw `EFI_STATUS` value, with the error bit set.
+    pub fn into_raw(self) -> efi_types::EFI_STATUS {
+        (self.code | ERROR_BIT) as _
+    }
+
+    /// Name of the error code as given in the UEFI specification.
+    pub fn name(&self) -> Option<&'static str> {
+        Some(match self.code {
+            1 => "EFI_LOAD_ERROR",
+            2 => "EFI_INVALID_PARAMETER",
+            3 => "EFI_UNSUPPORTED",
+            4 => "EFI_BAD_BUFFER_SIZE",
+            5 => "EFI_BUFFER_TOO_SMALL",
+            6 => "EFI_NOT_READY",
+            7 => "EFI_DEVICE_ERROR",
+            8 => "EFI_WRITE_PROTECTED",
+            9 => "EFI_OUT_OF_RESOURCES",
+            10 => "EFI_VOLUME_CORRUPTED",
+            11 => "EFI_VOLUME_FULL",
+            12 => "EFI_NO_MEDIA",
+            13 => "EFI_MEDIA_CHANGED",
+            14 => "EFI_NOT_FOUND",
+            15 => "EFI_ACCESS_DENIED",
+            16 => "EFI_NO_RESPONSE",
+            17 => "EFI_NO_MAPPING",
+            18 => "EFI_TIMEOUT",
+            19 => "EFI_NOT_STARTED",
+            20 => "EFI_ALREADY_STARTED",
+            21 => "EFI_ABORTED",
+            22 => "EFI_ICMP_ERROR",
+            23 => "EFI_TFTP_ERROR",
+            24 => "EFI_PROTOCOL_ERROR",
+            25 => "EFI_INCOMPATIBLE_VERSION",
+            26 => "EFI_SECURITY_VIOLATION",
+            27 => "EFI_CRC_ERROR",
+            28 => "EFI_END_OF_MEDIA",
+            31 => "EFI_END_OF_FILE",
+            32 => "EFI_INVALID_LANGUAGE",
+            33 => "EFI_COMPROMISED_DATA",
+            34 => "EFI_IP_ADDRESS_CONFLICT",
+            35 => "EFI_HTTP_ERROR",
+            _ => return None,
+        })
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.name() {
+            Some(name) => f.write_str(name),
+            None => write!(f, "EFI_STATUS({:#x})", self.code | ERROR_BIT),
+        }
+    }
+}
+
+impl fmt::Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Error({})", self)
+    }
+}
+
+impl From<Error> for efi_types::EFI_STATUS {
+    fn from(error: Error) -> efi_types::EFI_STATUS {
+        error.into_raw()
     }
 }
 
@@ -68,36 +408,137 @@ impl TryFrom<u8> for Color {
 
 pub type Result<T> = core::result::Result<T, Error>;
 
-pub(super) fn status_to_status<N: Into<u64>>(status: N) -> Result<Status> {
-    let status: u64 = status.into();
+pub(super) fn status_to_status(status: efi_types::EFI_STATUS) -> Result<Status> {
+    Status::from_raw(status)
+}
 
-    if core::mem::size_of::<N>() == 8 {
-        if status & 0x8000000000000000 != 0 {
-            return Err(Error{code: (status ^ 0x8000000000000000) as _});
-        }
+pub(super) fn status_to_result<T>(status: efi_types::EFI_STATUS, val: T) -> Result<T> {
+    Status::from_raw(status).map(|_| val)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+    use super::*;
+
+    const SAMPLE: &str = "00112233-4455-6677-8899-aabbccddeeff";
+
+    #[test]
+    fn guid_round_trip() {
+        let guid = Guid::parse(SAMPLE).unwrap();
+        assert_eq!(guid, Guid::new(0x00112233, 0x4455, 0x6677, [0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff]));
+        assert_eq!(guid.to_string(), SAMPLE);
+        assert_eq!(Guid::parse("00112233-4455-6677-8899-AABBCCDDEEFF"), Some(guid));
+        assert_eq!(alloc::format!("{:?}", guid), "Guid(00112233-4455-6677-8899-aabbccddeeff)");
     }
-    if core::mem::size_of::<N>() == 4 {
-        if status & 0x80000000 != 0 {
-            return Err(Error{code: (status ^ 0x80000000) as _});
-        }
+
+    #[test]
+    fn guid_byte_order() {
+        // The first three fields are little-endian in memory, the rest is kept in order.
+        let guid = Guid::parse(SAMPLE).unwrap();
+        let bytes = [0x33, 0x22, 0x11, 0x00, 0x55, 0x44, 0x77, 0x66,
+                     0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff];
+        assert_eq!(guid.to_bytes(), bytes);
+        assert_eq!(Guid::from_bytes(bytes), guid);
     }
 
-    return Ok(Status{code: status as _});
-}
+    #[test]
+    fn guid_from_str() {
+        let guid = Guid::parse(SAMPLE).unwrap();
+        assert_eq!(SAMPLE.parse(), Ok(guid));
+        assert_eq!("{00112233-4455-6677-8899-aabbccddeeff}".parse(), Ok(guid));
+        assert_eq!("{00112233-4455-6677-8899-aabbccddeeff".parse::<Guid>(), Err(ParseGuidError));
+        assert_eq!("{}".parse::<Guid>(), Err(ParseGuidError));
+    }
 
-pub(super) fn status_to_result<T, N: Into<u64>>(status: N, val: T) -> Result<T> {
-    let status: u64 = status.into();
+    #[test]
+    fn guid_rejects_malformed() {
+        // Wrong length.
+        assert_eq!(Guid::parse(""), None);
+        assert_eq!(Guid::parse("00112233-4455-6677-8899-aabbccddeef"), None);
+        assert_eq!(Guid::parse("00112233-4455-6677-8899-aabbccddeeff0"), None);
+        // Misplaced or missing dashes.
+        assert_eq!(Guid::parse("001122334-455-6677-8899-aabbccddeeff"), None);
+        assert_eq!(Guid::parse("001122330445506677088990aabbccddeeff"), None);
+        // Bad hex digits, including ones `from_str_radix` would take.
+        assert_eq!(Guid::parse("0011223g-4455-6677-8899-aabbccddeeff"), None);
+        assert_eq!(Guid::parse("00112233-4455-6677-8899-aabbccddeef "), None);
+        assert_eq!(Guid::parse("+0112233-4455-6677-8899-aabbccddeeff"), None);
+        assert_eq!(Guid::parse("00112233-4455-6677-8899-aabbccddee\u{e9}"), None);
+    }
 
-    if core::mem::size_of::<N>() == 8 {
-        if status & 0x8000000000000000 != 0 {
-            return Err(Error{code: (status ^ 0x8000000000000000) as _});
-        }
+    #[test]
+    fn guid_macro() {
+        const GUID: Guid = guid!("387477c2-69c7-11d2-8e39-00a0c969723b");
+        assert_eq!(GUID, Guid::new(0x387477c2, 0x69c7, 0x11d2, [0x8e, 0x39, 0x00, 0xa0, 0xc9, 0x69, 0x72, 0x3b]));
+        assert_eq!(guid!(SAMPLE), Guid::parse(SAMPLE).unwrap());
+    }
+
+    // `EFI_STATUS` is as wide as a pointer, so the error bit moves with it.
+    #[cfg(target_pointer_width = "64")]
+    const RAW_BUFFER_TOO_SMALL: efi_types::EFI_STATUS = 0x8000_0000_0000_0005;
+    #[cfg(target_pointer_width = "32")]
+    const RAW_BUFFER_TOO_SMALL: efi_types::EFI_STATUS = 0x8000_0005;
+
+    #[test]
+    fn status_split() {
+        assert_eq!(Status::from_raw(0), Ok(Status::SUCCESS));
+        assert!(Status::SUCCESS.is_success() && !Status::SUCCESS.is_warning());
+
+        assert_eq!(Status::from_raw(4), Ok(Status::WARN_BUFFER_TOO_SMALL));
+        assert!(Status::WARN_BUFFER_TOO_SMALL.is_warning() && !Status::WARN_BUFFER_TOO_SMALL.is_success());
+
+        assert_eq!(Status::from_raw(RAW_BUFFER_TOO_SMALL), Err(Error::BUFFER_TOO_SMALL));
+        assert_eq!(Error::BUFFER_TOO_SMALL.into_raw(), RAW_BUFFER_TOO_SMALL);
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn error_bit_is_the_top_bit() {
+        // The 32-bit error bit is an ordinary code bit on 64-bit targets.
+        assert_eq!(Status::from_raw(0x8000_0005), Ok(Status{code: 0x8000_0005}));
+        assert_eq!(Status::from_raw(!0), Err(Error{code: usize::MAX >> 1}));
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "32")]
+    fn error_bit_is_the_top_bit() {
+        assert_eq!(Status::from_raw(0x4000_0005), Ok(Status{code: 0x4000_0005}));
+        assert_eq!(Status::from_raw(!0), Err(Error{code: usize::MAX >> 1}));
     }
-    if core::mem::size_of::<N>() == 4 {
-        if status & 0x80000000 != 0 {
-            return Err(Error{code: (status ^ 0x80000000) as _});
+
+    #[test]
+    fn raw_round_trip() {
+        let codes = [0, 1, 7, 8, 35, 36, 0x1000, usize::MAX >> 1];
+        for &code in &codes {
+            let status = Status{code: code};
+            assert_eq!(Status::from_raw(status.into_raw()), Ok(status));
+            assert_eq!(efi_types::EFI_STATUS::from(status), status.into_raw());
+
+            let error = Error{code: code};
+            assert_eq!(Status::from_raw(error.into_raw()), Err(error));
+            assert_eq!(efi_types::EFI_STATUS::from(error), error.into_raw());
         }
     }
 
-    return Ok(val);
+    #[test]
+    fn status_helpers() {
+        assert_eq!(status_to_result(0, 42), Ok(42));
+        assert_eq!(status_to_result(4, 42), Ok(42));
+        assert_eq!(status_to_result(RAW_BUFFER_TOO_SMALL, 42), Err(Error::BUFFER_TOO_SMALL));
+
+        assert_eq!(status_to_status(0), Ok(Status::SUCCESS));
+        assert_eq!(status_to_status(4), Ok(Status::WARN_BUFFER_TOO_SMALL));
+        assert_eq!(status_to_status(RAW_BUFFER_TOO_SMALL), Err(Error::BUFFER_TOO_SMALL));
+    }
+
+    #[test]
+    fn status_display() {
+        assert_eq!(Status::SUCCESS.to_string(), "EFI_SUCCESS");
+        assert_eq!(Status::WARN_RESET_REQUIRED.to_string(), "EFI_WARN_RESET_REQUIRED");
+        assert_eq!(Status{code: 8}.to_string(), "EFI_STATUS(0x8)");
+        assert_eq!(Error::HTTP_ERROR.to_string(), "EFI_HTTP_ERROR");
+        assert_eq!(Error{code: 29}.name(), None);
+        assert_eq!(Error{code: 29}.to_string(), alloc::format!("EFI_STATUS({:#x})", ERROR_BIT | 29));
+    }
 }
@@ -0,0 +1,155 @@
+use core;
+use core::mem;
+use core::ops::{Deref, DerefMut};
+use core::ptr;
+use core::slice;
+
+use globals;
+use protocol::{Error, Result};
+use protocol::boot_services::MemoryType;
+
+/// Frees a pool buffer, unless boot services are gone already.
+///
+/// After `ExitBootServices()` all memory belongs to the application,
+/// so leaking the buffer is the only sensible thing to do.
+unsafe fn free(p: *mut u8) {
+    if let Some(table) = globals::BOOT_SERVICES_TABLE {
+        let _ = table.free_pool(p);
+    }
+}
+
+/// Allocates `size` bytes of `LoaderData` pool suitable for holding `T`s.
+///
+/// Fails with `EFI_UNSUPPORTED` after `ExitBootServices()`.
+fn allocate<T>(size: usize) -> Result<*mut T> {
+    // Pool allocations are 8-byte aligned.
+    assert!(mem::align_of::<T>() <= 8);
+
+    let table = match unsafe { globals::BOOT_SERVICES_TABLE } {
+        Some(table) => table,
+        None => return Err(Error::UNSUPPORTED),
+    };
+    let p = table.allocate_pool(MemoryType::LoaderData, size)?;
+    Ok(p as *mut T)
+}
+
+/// A single value stored in pool memory, freed with `FreePool()` on drop.
+pub struct PoolBox<T> {
+    ptr: *mut T,
+}
+
+impl<T> PoolBox<T> {
+    /// Moves `value` into newly allocated pool memory.
+    pub fn new(value: T) -> Result<PoolBox<T>> {
+        let p = allocate::<T>(mem::size_of::<T>())?;
+        unsafe { ptr::write(p, value) };
+        Ok(PoolBox{ ptr: p })
+    }
+
+    /// Takes ownership of a buffer allocated with `AllocatePool()`.
+    pub unsafe fn from_raw(ptr: *mut T) -> PoolBox<T> {
+        PoolBox{ ptr: ptr }
+    }
+
+    /// Releases ownership of the buffer without freeing it.
+    pub fn into_raw(b: PoolBox<T>) -> *mut T {
+        let p = b.ptr;
+        mem::forget(b);
+        p
+    }
+}
+
+impl<T> Deref for PoolBox<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.ptr }
+    }
+}
+
+impl<T> DerefMut for PoolBox<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.ptr }
+    }
+}
+
+impl<T> Drop for PoolBox<T> {
+    fn drop(&mut self) {
+        unsafe {
+            ptr::drop_in_place(self.ptr);
+            free(self.ptr as *mut u8);
+        }
+    }
+}
+
+/// An array stored in pool memory, such as a handle buffer returned by the firmware.
+pub struct PoolSlice<T> {
+    ptr: *mut T,
+    len: usize,
+}
+
+impl<T> PoolSlice<T> {
+    /// Allocates pool memory for `len` values, initialized by cloning `value`.
+    ///
+    /// Fails with `EFI_BAD_BUFFER_SIZE` if the size in bytes doesn't fit in `usize`.
+    pub fn from_elem(value: T, len: usize) -> Result<PoolSlice<T>> where T: Clone {
+        let size = mem::size_of::<T>().checked_mul(len).ok_or(Error::BAD_BUFFER_SIZE)?;
+        let p = allocate::<T>(size)?;
+        for i in 0..len {
+            unsafe { ptr::write(p.offset(i as isize), value.clone()) };
+        }
+        Ok(PoolSlice{ ptr: p, len: len })
+    }
+
+    /// Takes ownership of a buffer of `len` values allocated with `AllocatePool()`.
+    ///
+    /// A null `ptr` is accepted if `len` is zero.
+    pub unsafe fn from_raw(ptr: *mut T, len: usize) -> PoolSlice<T> {
+        PoolSlice{ ptr: ptr, len: len }
+    }
+
+    /// Releases ownership of the buffer without freeing it.
+    pub fn into_raw(s: PoolSlice<T>) -> (*mut T, usize) {
+        let raw = (s.ptr, s.len);
+        mem::forget(s);
+        raw
+    }
+}
+
+impl<T> Deref for PoolSlice<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        if self.ptr.is_null() {
+            return &[];
+        }
+        unsafe { slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl<T> DerefMut for PoolSlice<T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        if self.ptr.is_null() {
+            return &mut [];
+        }
+        unsafe { slice::from_raw_parts_mut(self.ptr, self.len) }
+    }
+}
+
+impl<T> Drop for PoolSlice<T> {
+    fn drop(&mut self) {
+        if self.ptr.is_null() {
+            return;
+        }
+        unsafe {
+            ptr::drop_in_place(&mut **self as *mut [T]);
+            free(self.ptr as *mut u8);
+        }
+    }
+}
+
+impl<T: core::fmt::Debug> core::fmt::Debug for PoolSlice<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        (**self).fmt(f)
+    }
+}
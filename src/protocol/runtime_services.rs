@@ -0,0 +1,34 @@
+use efi_types;
+use core::ptr;
+
+#[repr(C)]
+pub enum ResetType {
+    Cold,
+    Warm,
+    Shutdown,
+    PlatformSpecific,
+}
+
+/// Services that remain available after `ExitBootServices()`.
+#[repr(C)]
+pub struct RuntimeServices {
+    table: efi_types::EFI_RUNTIME_SERVICES,
+}
+
+impl RuntimeServices {
+    /// Resets the entire platform.
+    ///
+    /// ```text
+    ///     The `reset_system()` function resets the entire platform, including
+    ///     all processors and devices, and reboots the system.
+    ///     Calling this interface with `ResetType` of `Cold` causes a system-wide
+    ///     reset. `Warm` resets the processors without resetting the devices.
+    ///     `Shutdown` places the system into a state equivalent to ACPI G2/S5 or G3.
+    /// ```
+    ///
+    pub fn reset_system(&mut self, reset_type: ResetType) -> ! {
+        let func = self.table.ResetSystem.unwrap();
+        unsafe { func(reset_type as _, efi_types::EFI_SUCCESS as _, 0, ptr::null_mut()) };
+        unreachable!()
+    }
+}
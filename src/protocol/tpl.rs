@@ -0,0 +1,65 @@
+use globals;
+
+/// Task priority level.
+///
+/// Code running at a given level can only be interrupted by event
+/// notification functions registered at a higher level.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Tpl(pub(crate) usize);
+
+impl Tpl {
+    /// The level applications normally run at.
+    pub const APPLICATION: Tpl = Tpl(4);
+    pub const CALLBACK: Tpl = Tpl(8);
+    pub const NOTIFY: Tpl = Tpl(16);
+    /// Interrupts are disabled at this level.
+    pub const HIGH_LEVEL: Tpl = Tpl(31);
+}
+
+/// Restores the previous task priority level when dropped.
+///
+/// Returned by `BootServices::raise_tpl()`.
+#[must_use]
+pub struct TplGuard {
+    old: Tpl,
+}
+
+impl TplGuard {
+    pub(crate) fn new(old: Tpl) -> TplGuard {
+        TplGuard{ old: old }
+    }
+
+    /// The level that will be restored.
+    pub fn old_tpl(&self) -> Tpl {
+        self.old
+    }
+}
+
+impl Drop for TplGuard {
+    fn drop(&mut self) {
+        unsafe {
+            if let Some(table) = globals::BOOT_SERVICES_TABLE {
+                table.restore_tpl(self.old);
+            }
+        }
+    }
+}
+
+/// Raises the task priority level to at least `Tpl::NOTIFY`, if boot services are active.
+///
+/// Used to protect the crate's global state from being re-entered by event
+/// notification functions. `Tpl::NOTIFY` is the highest level at which memory
+/// allocation and console output are allowed, so whatever is done under the
+/// guard may still use them.
+pub(crate) fn protect_global_state() -> Option<TplGuard> {
+    let table = match unsafe { globals::BOOT_SERVICES_TABLE } {
+        Some(table) => table,
+        None => return None,
+    };
+
+    if table.current_tpl() >= Tpl::NOTIFY {
+        None
+    } else {
+        Some(TplGuard::new(unsafe { table.raise_tpl_raw(Tpl::NOTIFY) }))
+    }
+}
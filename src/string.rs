@@ -0,0 +1,295 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::fmt;
+use core::ops::Deref;
+use core::slice;
+use core::str::FromStr;
+
+use ucs2;
+
+/// Error returned when a string can't be converted to a `CStr16` or `CString16`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NulError {
+    /// The string contains a NUL at the given position, before its end.
+    InteriorNul(usize),
+    /// The slice is not terminated with a NUL.
+    NotNulTerminated,
+}
+
+/// Borrowed NUL-terminated UCS-2 string, as taken and returned by firmware (`CHAR16*`).
+pub struct CStr16 {
+    // Includes the terminator.
+    inner: [u16],
+}
+
+impl CStr16 {
+    /// Wraps a slice that ends with the only NUL in it.
+    pub fn from_u16_with_nul(units: &[u16]) -> Result<&CStr16, NulError> {
+        match units.iter().position(|&u| u == 0) {
+            Some(i) if i == units.len() - 1 => Ok(unsafe { CStr16::from_u16_with_nul_unchecked(units) }),
+            Some(i) => Err(NulError::InteriorNul(i)),
+            None => Err(NulError::NotNulTerminated),
+        }
+    }
+
+    /// Wraps a slice without checking that it ends with the only NUL in it.
+    pub unsafe fn from_u16_with_nul_unchecked(units: &[u16]) -> &CStr16 {
+        &*(units as *const [u16] as *const CStr16)
+    }
+
+    /// Wraps a string returned by the firmware.
+    ///
+    /// The string must stay valid and unmodified for the lifetime `'a`.
+    pub unsafe fn from_ptr<'a>(p: *const u16) -> &'a CStr16 {
+        let len = ucs2::strlen(p);
+        CStr16::from_u16_with_nul_unchecked(slice::from_raw_parts(p, len + 1))
+    }
+
+    /// Pointer to pass to the firmware.
+    pub fn as_ptr(&self) -> *const u16 {
+        self.inner.as_ptr()
+    }
+
+    /// The code units, without the terminator.
+    pub fn as_slice(&self) -> &[u16] {
+        &self.inner[..self.inner.len() - 1]
+    }
+
+    /// The code units, including the terminator.
+    pub fn as_slice_with_nul(&self) -> &[u16] {
+        &self.inner
+    }
+
+    /// Number of code units, not counting the terminator.
+    pub fn len(&self) -> usize {
+        self.inner.len() - 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Decodes the string. Unpaired surrogates are decoded as U+FFFD.
+    pub fn chars(&self) -> ucs2::Decode {
+        ucs2::decode(self.as_slice())
+    }
+}
+
+impl PartialEq for CStr16 {
+    fn eq(&self, other: &CStr16) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl Eq for CStr16 {}
+
+impl PartialEq<str> for CStr16 {
+    fn eq(&self, other: &str) -> bool {
+        self.chars().eq(other.chars())
+    }
+}
+
+impl<'a> PartialEq<&'a str> for CStr16 {
+    fn eq(&self, other: &&'a str) -> bool {
+        *self == **other
+    }
+}
+
+impl fmt::Display for CStr16 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for c in self.chars() {
+            fmt::Write::write_char(f, c)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for CStr16 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("\"")?;
+        for c in self.chars() {
+            for e in c.escape_debug() {
+                fmt::Write::write_char(f, e)?;
+            }
+        }
+        f.write_str("\"")
+    }
+}
+
+impl<'a> From<&'a CStr16> for String {
+    fn from(s: &'a CStr16) -> String {
+        s.chars().collect()
+    }
+}
+
+/// Owned NUL-terminated UCS-2 string, allocated on the heap.
+#[derive(Clone, PartialEq, Eq)]
+pub struct CString16 {
+    // Includes the terminator.
+    inner: Vec<u16>,
+}
+
+impl CString16 {
+    /// Encodes `s`. Characters outside of the Basic Multilingual Plane
+    /// are replaced with U+FFFD, as firmware strings are UCS-2.
+    pub fn new(s: &str) -> Result<CString16, NulError> {
+        CString16::with_policy(s, ucs2::Policy::Replace)
+    }
+
+    /// Encodes `s`, handling characters outside of the Basic Multilingual Plane according to `policy`.
+    pub fn with_policy(s: &str, policy: ucs2::Policy) -> Result<CString16, NulError> {
+        let mut inner: Vec<u16> = ucs2::Encoder::new(s, policy).collect();
+        if let Some(i) = inner.iter().position(|&u| u == 0) {
+            return Err(NulError::InteriorNul(i));
+        }
+        inner.push(0);
+        Ok(CString16{ inner: inner })
+    }
+
+    /// Takes ownership of code units, which must end with the only NUL in them.
+    pub fn from_vec_with_nul(units: Vec<u16>) -> Result<CString16, NulError> {
+        CStr16::from_u16_with_nul(&units)?;
+        Ok(CString16{ inner: units })
+    }
+
+    pub fn as_c_str(&self) -> &CStr16 {
+        unsafe { CStr16::from_u16_with_nul_unchecked(&self.inner) }
+    }
+
+    /// The code units, including the terminator.
+    pub fn into_vec_with_nul(self) -> Vec<u16> {
+        self.inner
+    }
+}
+
+impl Deref for CString16 {
+    type Target = CStr16;
+
+    fn deref(&self) -> &CStr16 {
+        self.as_c_str()
+    }
+}
+
+impl AsRef<CStr16> for CString16 {
+    fn as_ref(&self) -> &CStr16 {
+        self.as_c_str()
+    }
+}
+
+impl Borrow<CStr16> for CString16 {
+    fn borrow(&self) -> &CStr16 {
+        self.as_c_str()
+    }
+}
+
+impl<'a> From<&'a CStr16> for CString16 {
+    fn from(s: &'a CStr16) -> CString16 {
+        CString16{ inner: s.as_slice_with_nul().to_vec() }
+    }
+}
+
+impl From<CString16> for String {
+    fn from(s: CString16) -> String {
+        s.chars().collect()
+    }
+}
+
+impl FromStr for CString16 {
+    type Err = NulError;
+
+    fn from_str(s: &str) -> Result<CString16, NulError> {
+        CString16::new(s)
+    }
+}
+
+impl PartialEq<str> for CString16 {
+    fn eq(&self, other: &str) -> bool {
+        *self.as_c_str() == *other
+    }
+}
+
+impl<'a> PartialEq<&'a str> for CString16 {
+    fn eq(&self, other: &&'a str) -> bool {
+        *self.as_c_str() == **other
+    }
+}
+
+impl fmt::Display for CString16 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self.as_c_str(), f)
+    }
+}
+
+impl fmt::Debug for CString16 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_c_str(), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::{String, ToString};
+    use alloc::vec::Vec;
+    use ucs2;
+    use super::*;
+
+    #[test]
+    fn from_u16_with_nul() {
+        let s = CStr16::from_u16_with_nul(&[0x61, 0x62, 0]).unwrap();
+        assert_eq!(s.len(), 2);
+        assert_eq!(s.as_slice(), &[0x61, 0x62]);
+        assert_eq!(s.as_slice_with_nul(), &[0x61, 0x62, 0]);
+        assert!(CStr16::from_u16_with_nul(&[0]).unwrap().is_empty());
+
+        assert_eq!(CStr16::from_u16_with_nul(&[0x61, 0, 0x62, 0]).err(), Some(NulError::InteriorNul(1)));
+        assert_eq!(CStr16::from_u16_with_nul(&[0x61, 0x62]).err(), Some(NulError::NotNulTerminated));
+        assert_eq!(CStr16::from_u16_with_nul(&[]).err(), Some(NulError::NotNulTerminated));
+    }
+
+    #[test]
+    fn from_ptr_stops_at_nul() {
+        let units = [0x61, 0x62, 0, 0x63, 0];
+        let s = unsafe { CStr16::from_ptr(units.as_ptr()) };
+        assert_eq!(s.as_slice_with_nul(), &units[..3]);
+    }
+
+    #[test]
+    fn new() {
+        let s = CString16::new("a\u{e9}").unwrap();
+        assert_eq!(s.as_slice_with_nul(), &[0x61, 0xe9, 0]);
+        assert_eq!(CString16::new("a\0b").err(), Some(NulError::InteriorNul(1)));
+        assert_eq!(CString16::new("").unwrap().as_slice_with_nul(), &[0]);
+    }
+
+    #[test]
+    fn policies() {
+        let s = CString16::new("a\u{1f600}").unwrap();
+        assert_eq!(s.as_slice_with_nul(), &[0x61, ucs2::REPLACEMENT_CHARACTER, 0]);
+        let s = CString16::with_policy("a\u{1f600}", ucs2::Policy::Surrogates).unwrap();
+        assert_eq!(s.as_slice_with_nul(), &[0x61, 0xd83d, 0xde00, 0]);
+    }
+
+    #[test]
+    fn from_vec_with_nul() {
+        let mut units = Vec::new();
+        units.extend_from_slice(&[0x61, 0x62, 0]);
+        assert_eq!(CString16::from_vec_with_nul(units).unwrap(), "ab");
+
+        let mut units = Vec::new();
+        units.extend_from_slice(&[0x61, 0x62]);
+        assert_eq!(CString16::from_vec_with_nul(units).err(), Some(NulError::NotNulTerminated));
+    }
+
+    #[test]
+    fn display() {
+        let s = CString16::with_policy("\u{e9}t\u{e9} \u{1f600}", ucs2::Policy::Surrogates).unwrap();
+        assert_eq!(s.to_string(), "\u{e9}t\u{e9} \u{1f600}");
+        assert_eq!(s.as_c_str().to_string(), "\u{e9}t\u{e9} \u{1f600}");
+        assert_eq!(String::from(s), "\u{e9}t\u{e9} \u{1f600}");
+
+        // An unpaired surrogate.
+        let s = CStr16::from_u16_with_nul(&[0x61, 0xd800, 0]).unwrap();
+        assert_eq!(s.to_string(), "a\u{fffd}");
+    }
+}
@@ -0,0 +1,305 @@
+use core::char;
+use core::iter::{Cloned, Peekable};
+use core::slice;
+use core::str::Chars;
+
+pub const REPLACEMENT_CHARACTER: u16 = 0xfffd;
+
+/// How to encode characters outside of the Basic Multilingual Plane.
+///
+/// UEFI strings are nominally UCS-2, and a lot of firmware renders surrogate
+/// pairs as two garbage glyphs, or rejects them outright.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Policy {
+    /// Replace such characters with U+FFFD, producing valid UCS-2.
+    Replace,
+    /// Encode such characters as surrogate pairs, producing UTF-16.
+    Surrogates,
+}
+
+/// Encodes a single character, returning the code units and how many of them are used.
+#[inline]
+pub fn encode_char(c: char, policy: Policy) -> ([u16; 2], usize) {
+    let code = c as u32;
+
+    if code < 0x10000 {
+        return ([code as u16, 0], 1);
+    }
+
+    match policy {
+        Policy::Replace => ([REPLACEMENT_CHARACTER, 0], 1),
+        Policy::Surrogates => {
+            let code = code - 0x10000;
+            let high_surrogate = 0xd800 + (code >> 10);
+            let low_surrogate = 0xdc00 + (code & 0x03ff);
+            ([high_surrogate as u16, low_surrogate as u16], 2)
+        },
+    }
+}
+
+/// Streaming encoder from `&str` to UCS-2 or UTF-16.
+///
+/// Can be used either as an iterator over code units, or to fill
+/// fixed-size NUL-terminated buffers with `encode_chunk()`.
+pub struct Encoder<'a> {
+    chars: Chars<'a>,
+    policy: Policy,
+    expand_newlines: bool,
+    // Units of a character that didn't fit in the last chunk.
+    lookahead: Option<([u16; 2], usize)>,
+    // Second unit of a character partially returned by `next()`.
+    pending: Option<u16>,
+}
+
+impl<'a> Encoder<'a> {
+    pub fn new(s: &'a str, policy: Policy) -> Self {
+        Encoder {
+            chars: s.chars(),
+            policy: policy,
+            expand_newlines: false,
+            lookahead: None,
+            pending: None,
+        }
+    }
+
+    /// Whether to encode `"\n"` as `"\r\n"`, as expected by text consoles.
+    pub fn expand_newlines(mut self, expand: bool) -> Self {
+        self.expand_newlines = expand;
+        self
+    }
+
+    fn next_units(&mut self) -> Option<([u16; 2], usize)> {
+        if let Some(units) = self.lookahead.take() {
+            return Some(units);
+        }
+
+        self.chars.next().map(|c| {
+            if self.expand_newlines && c == '\n' {
+                (['\r' as u16, '\n' as u16], 2)
+            } else {
+                encode_char(c, self.policy)
+            }
+        })
+    }
+
+    /// Encodes as much of the rest of the string as fits into `buffer`,
+    /// followed by a NUL terminator.
+    ///
+    /// Surrogate pairs and expanded newlines are never split between chunks.
+    /// Returns the number of code units written, not counting the terminator,
+    /// which is zero once the whole string has been encoded.
+    ///
+    /// Panics if `buffer` can't hold at least two code units and the terminator.
+    pub fn encode_chunk(&mut self, buffer: &mut [u16]) -> usize {
+        assert!(buffer.len() >= 3);
+
+        let mut i = 0;
+
+        if let Some(unit) = self.pending.take() {
+            buffer[i] = unit;
+            i += 1;
+        }
+
+        while let Some((units, n)) = self.next_units() {
+            if i + n >= buffer.len() {
+                self.lookahead = Some((units, n));
+                break;
+            }
+            buffer[i..i+n].copy_from_slice(&units[..n]);
+            i += n;
+        }
+
+        buffer[i] = 0;
+        i
+    }
+}
+
+impl<'a> Iterator for Encoder<'a> {
+    type Item = u16;
+
+    fn next(&mut self) -> Option<u16> {
+        if let Some(unit) = self.pending.take() {
+            return Some(unit);
+        }
+
+        self.next_units().map(|(units, n)| {
+            if n == 2 {
+                self.pending = Some(units[1]);
+            }
+            units[0]
+        })
+    }
+}
+
+/// Iterator over the characters of UCS-2 or UTF-16 code units, returned by `decode_units()`.
+///
+/// Decoding stops at the first NUL. Unpaired surrogates are decoded as U+FFFD.
+pub struct DecodeUnits<I: Iterator<Item = u16>> {
+    units: Peekable<I>,
+}
+
+impl<I: Iterator<Item = u16>> Iterator for DecodeUnits<I> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        let unit = match self.units.next() {
+            None | Some(0) => return None,
+            Some(unit) => unit,
+        };
+
+        let code = match unit {
+            0xd800..=0xdbff => match self.units.peek() {
+                Some(&low @ 0xdc00..=0xdfff) => {
+                    self.units.next();
+                    0x10000 + ((((unit - 0xd800) as u32) << 10) | ((low - 0xdc00) as u32))
+                },
+                _ => REPLACEMENT_CHARACTER as u32,
+            },
+            0xdc00..=0xdfff => REPLACEMENT_CHARACTER as u32,
+            _ => unit as u32,
+        };
+
+        Some(char::from_u32(code).unwrap())
+    }
+}
+
+/// Iterator over the characters of a UCS-2 or UTF-16 string, returned by `decode()`.
+pub type Decode<'a> = DecodeUnits<Cloned<slice::Iter<'a, u16>>>;
+
+/// Decodes a UCS-2 or UTF-16 string returned by the firmware.
+pub fn decode(units: &[u16]) -> Decode {
+    decode_units(units.iter().cloned())
+}
+
+/// Decodes code units that aren't in a `&[u16]`, such as unaligned ones.
+pub fn decode_units<I: IntoIterator<Item = u16>>(units: I) -> DecodeUnits<I::IntoIter> {
+    DecodeUnits{ units: units.into_iter().peekable() }
+}
+
+/// Returns the number of code units before the NUL terminator.
+pub unsafe fn strlen(mut p: *const u16) -> usize {
+    let mut len = 0;
+    while *p != 0 {
+        len += 1;
+        p = p.offset(1);
+    }
+    len
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::String;
+    use alloc::vec::Vec;
+    use super::*;
+
+    fn encode(s: &str, policy: Policy) -> Vec<u16> {
+        Encoder::new(s, policy).collect()
+    }
+
+    #[test]
+    fn surrogate_pairs() {
+        assert_eq!(encode_char('\u{1f600}', Policy::Surrogates), ([0xd83d, 0xde00], 2));
+        assert_eq!(encode_char('\u{10000}', Policy::Surrogates), ([0xd800, 0xdc00], 2));
+        assert_eq!(encode_char('\u{10ffff}', Policy::Surrogates), ([0xdbff, 0xdfff], 2));
+    }
+
+    #[test]
+    fn bmp_boundary() {
+        for &policy in &[Policy::Replace, Policy::Surrogates] {
+            assert_eq!(encode_char('\u{ffff}', policy), ([0xffff, 0], 1));
+            assert_eq!(encode_char('\u{e000}', policy), ([0xe000, 0], 1));
+        }
+    }
+
+    #[test]
+    fn policies() {
+        assert_eq!(encode("a\u{e9}\u{1f600}", Policy::Replace), [0x61, 0xe9, REPLACEMENT_CHARACTER]);
+        assert_eq!(encode("a\u{e9}\u{1f600}", Policy::Surrogates), [0x61, 0xe9, 0xd83d, 0xde00]);
+    }
+
+    #[test]
+    fn expand_newlines() {
+        let units: Vec<u16> = Encoder::new("a\nb", Policy::Replace).expand_newlines(true).collect();
+        assert_eq!(units, [0x61, 0x0d, 0x0a, 0x62]);
+        assert_eq!(encode("a\nb", Policy::Replace), [0x61, 0x0a, 0x62]);
+    }
+
+    #[test]
+    fn encode_chunk_keeps_pairs_together() {
+        let mut encoder = Encoder::new("ab\u{1f600}c\n", Policy::Surrogates).expand_newlines(true);
+        let mut buffer = [0xffff; 4];
+
+        assert_eq!(encoder.encode_chunk(&mut buffer), 2);
+        assert_eq!(buffer[..3], [0x61, 0x62, 0]);
+        assert_eq!(encoder.encode_chunk(&mut buffer), 3);
+        assert_eq!(buffer, [0xd83d, 0xde00, 0x63, 0]);
+        assert_eq!(encoder.encode_chunk(&mut buffer), 2);
+        assert_eq!(buffer[..3], [0x0d, 0x0a, 0]);
+        assert_eq!(encoder.encode_chunk(&mut buffer), 0);
+        assert_eq!(buffer[0], 0);
+    }
+
+    #[test]
+    fn encode_chunk_every_size() {
+        let s = "x\u{1f600}\u{1f600}yz\u{10437}";
+        let expected = encode(s, Policy::Surrogates);
+
+        for size in 3..expected.len() + 2 {
+            let mut encoder = Encoder::new(s, Policy::Surrogates);
+            let mut buffer = Vec::new();
+            buffer.resize(size, 0);
+            let mut units = Vec::new();
+            loop {
+                let n = encoder.encode_chunk(&mut buffer);
+                if n == 0 {
+                    break;
+                }
+                assert_eq!(buffer[n], 0);
+                // A chunk never ends with a high surrogate.
+                assert!(!(0xd800..0xdc00).contains(&buffer[n - 1]));
+                units.extend_from_slice(&buffer[..n]);
+            }
+            assert_eq!(units, expected);
+        }
+    }
+
+    #[test]
+    fn decode_pairs() {
+        let s: String = decode(&[0x61, 0xd83d, 0xde00, 0xe9]).collect();
+        assert_eq!(s, "a\u{1f600}\u{e9}");
+    }
+
+    #[test]
+    fn decode_unpaired_surrogates() {
+        // High surrogate at the end, high surrogate followed by a non-surrogate, lone low surrogate.
+        let s: String = decode(&[0x61, 0xd83d]).collect();
+        assert_eq!(s, "a\u{fffd}");
+        let s: String = decode(&[0xd83d, 0x62]).collect();
+        assert_eq!(s, "\u{fffd}b");
+        let s: String = decode(&[0xde00, 0xd83d, 0xd83d, 0xde00]).collect();
+        assert_eq!(s, "\u{fffd}\u{fffd}\u{1f600}");
+    }
+
+    #[test]
+    fn decode_stops_at_nul() {
+        let s: String = decode(&[0x61, 0x62, 0, 0x63]).collect();
+        assert_eq!(s, "ab");
+        assert_eq!(decode(&[]).count(), 0);
+    }
+
+    #[test]
+    fn decode_units_matches_decode() {
+        let units = [0x61, 0xd83d, 0xde00, 0xdc00, 0xd800, 0x62, 0, 0x63];
+        let from_slice: String = decode(&units).collect();
+        let from_iter: String = decode_units(units.iter().cloned()).collect();
+        assert_eq!(from_slice, "a\u{1f600}\u{fffd}\u{fffd}b");
+        assert_eq!(from_iter, from_slice);
+    }
+
+    #[test]
+    fn strlen_counts_to_nul() {
+        let units = [0x61, 0xd83d, 0xde00, 0, 0x62, 0];
+        assert_eq!(unsafe { strlen(units.as_ptr()) }, 3);
+        assert_eq!(unsafe { strlen(units[3..].as_ptr()) }, 0);
+    }
+}